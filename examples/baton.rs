@@ -5,7 +5,7 @@ extern crate pscontroller_rs;
 use linux_hal::spidev::{SpiModeFlags, SpidevOptions};
 use linux_hal::{CdevPin, SpidevBus};
 
-use pscontroller_rs::PlayStationPort;
+use pscontroller_rs::{baton::Baton, PlayStationPort};
 
 // Specific to the host device used on Linux, you'll have to change the following
 // parameters depending on your board and also export and allow writing to the GPIO
@@ -29,8 +29,6 @@ fn main() {
     let spi = build_spi().unwrap();
     let mut psp: PlayStationPort<_, CdevPin> = PlayStationPort::new(spi, None);
 
-    let mut controller;
-
     psp.enable_pressure().unwrap();
 
     loop {
@@ -44,9 +42,13 @@ fn main() {
 
         // It's not possible to tell the baton apart from a NeGcon or other
         // devices that respond as type "0x2", so we have to force it for now.
-        unsafe {
-            controller = controller_data.b;
-        }
+        let controller: Baton = match controller_data.decode() {
+            Err(_) => {
+                print!("\rNot enough data for a baton");
+                continue;
+            }
+            Ok(x) => x,
+        };
 
         println!(
             "\rA:{}, B:{} - Z: {:03} X: {:03} Y: {:03} A: {:03}",
@@ -5,7 +5,8 @@ extern crate pscontroller_rs;
 use linux_hal::spidev::{SpiModeFlags, SpidevOptions};
 use linux_hal::{CdevPin, SpidevBus};
 
-use pscontroller_rs::{classic::GamepadButtons, dualshock::ControlDS, Device, PlayStationPort};
+use pscontroller_rs::dualshock::{ControlDS, PressureButton};
+use pscontroller_rs::{classic::GamepadButtons, Device, PlayStationPort};
 
 // Specific to the host device used on Linux, you'll have to change the following
 // parameters depending on your board and also export and allow writing to the GPIO
@@ -26,18 +27,14 @@ fn build_spi() -> Result<SpidevBus, Box<dyn std::error::Error>> {
 }
 
 fn set_motors(buttons: &GamepadButtons, small: &mut bool, big: &mut u8) {
-    if buttons.cross() {
-        *small = true;
-    } else {
-        *small = false;
-    }
+    *small = buttons.cross();
 
     if buttons.down() {
         *big = 255 / 3;
     } else if buttons.left() {
         *big = 255 / 2;
     } else if buttons.up() {
-        *big = 255 / 1;
+        *big = 255;
     } else {
         *big = 0;
     }
@@ -66,7 +63,7 @@ fn main() {
         };
 
         match controller {
-            Device::DualShock(x) | Device::AnalogJoystick(x) => {
+            Device::DualShock(x) | Device::AnalogGreen(x) => {
                 println!(
                     "DualShock:   Start? {0} - R:{1:02x},{2:02x}, L:{3:02x},{4:02x}",
                     x.buttons.start(),
@@ -84,7 +81,7 @@ fn main() {
                     x.buttons.start(),
                     x.rx,
                     x.ry,
-                    x.pressures[6]
+                    x.pressure(PressureButton::Cross).unwrap_or(0)
                 );
 
                 set_motors(&x.buttons, &mut small, &mut big);
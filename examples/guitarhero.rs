@@ -5,7 +5,7 @@ extern crate pscontroller_rs;
 use linux_hal::spidev::{SpiModeFlags, SpidevOptions};
 use linux_hal::{CdevPin, SpidevBus};
 
-use pscontroller_rs::PlayStationPort;
+use pscontroller_rs::{guitarhero::GuitarHero, PlayStationPort};
 
 // Specific to the host device used on Linux, you'll have to change the following
 // parameters depending on your board and also export and allow writing to the GPIO
@@ -29,8 +29,6 @@ fn main() {
     let spi = build_spi().unwrap();
     let mut psp: PlayStationPort<_, CdevPin> = PlayStationPort::new(spi, None);
 
-    let mut controller;
-
     psp.enable_pressure().unwrap();
 
     loop {
@@ -45,9 +43,13 @@ fn main() {
         // We can't guess that we have a GuitarHero controller as it
         // appears nearly the same as a first-generation DualShock, except
         // for some buttons that are held down which isn't reliable
-        unsafe {
-            controller = controller_data.gh;
-        }
+        let controller: GuitarHero = match controller_data.decode() {
+            Err(_) => {
+                print!("\rNot enough data for a Guitar Hero controller");
+                continue;
+            }
+            Ok(x) => x,
+        };
 
         //        println!("G:{0:1b}, R:{0:1b}, Y:{0:1b}, B:{0:1b}, O:{0:1b}",
         println!(
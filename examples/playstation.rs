@@ -5,6 +5,7 @@ extern crate pscontroller_rs;
 use linux_hal::spidev::{SpiModeFlags, SpidevOptions};
 use linux_hal::{CdevPin, SpidevBus};
 
+use pscontroller_rs::dualshock::PressureButton;
 use pscontroller_rs::{Device, PlayStationPort};
 
 // Specific to the host device used on Linux, you'll have to change the following
@@ -53,7 +54,8 @@ fn main() {
     print!("Const 3.1: ");
     dump_hex(&config.const3a);
     print!("Const 3.2: ");
-    dump_hex(&config.const3a);
+    dump_hex(&config.const3b);
+    println!("{}", config);
 
     loop {
         let controller = match psp.read_input(None) {
@@ -77,7 +79,7 @@ fn main() {
                     x.buttons.right()
                 );
             }
-            Device::Classic(x) => {
+            Device::Classic(x) | Device::Digital(x) => {
                 println!(
                     "Classic - Start? {0}, Square? {1}",
                     x.buttons.start(),
@@ -88,7 +90,7 @@ fn main() {
                     psp.enable_pressure().unwrap();
                 }
             }
-            Device::AnalogJoystick(x) => {
+            Device::AnalogGreen(x) => {
                 println!(
                     "Analog - Start? {0} - R:{1:02x},{2:02x}, L:{3:02x},{4:02x}",
                     x.buttons.start(),
@@ -114,7 +116,7 @@ fn main() {
                     x.buttons.start(),
                     x.rx,
                     x.ry,
-                    x.pressures[6]
+                    x.pressure(PressureButton::Cross).unwrap_or(0)
                 );
             }
             Device::JogCon(x) => {
@@ -143,7 +145,10 @@ fn main() {
                 )
             }
             Device::ConfigurationMode => {
-                println!("Somehow we got stuck where we shouldn't be");
+                println!(
+                    "Stuck in escape mode and couldn't get out ({} recoveries so far)",
+                    psp.escape_recoveries()
+                );
             }
             _ => println!("Unimplemented"),
         }
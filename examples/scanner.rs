@@ -6,10 +6,9 @@ use linux_hal::spidev::{SpiModeFlags, SpidevOptions};
 use linux_hal::{CdevPin, SpidevBus};
 use std::{thread, time};
 
+use pscontroller_rs::command::Command;
 use pscontroller_rs::{MultitapPort, PlayStationPort};
 
-const CMD_ENTER_ESCAPE_MODE: &[u8] = &[0x00, 0x43, 0x00, 0x01, 0x00];
-const CMD_EXIT_ESCAPE_MODE: &[u8] = &[0x00, 0x43, 0x00, 0x00, 0x00];
 const MULTITAP_LIST: [MultitapPort; 4] = [
     MultitapPort::A,
     MultitapPort::B,
@@ -41,7 +40,6 @@ fn main() {
     let mut psp: PlayStationPort<_, CdevPin> = PlayStationPort::new(spi, None);
     let mut command = [0u8; SCAN_RESPONSE_WIDTH as usize];
     let mut buffer = [0u8; SCAN_RESPONSE_WIDTH as usize];
-    let mut _dummy = [0u8; SCAN_RESPONSE_WIDTH as usize];
 
     let sleep_duration = time::Duration::from_micros(SAMPLE_PAUSE);
 
@@ -76,26 +74,15 @@ fn main() {
                 command[1] = j;
 
                 if escape {
-                    psp.send_command(CMD_ENTER_ESCAPE_MODE, &mut _dummy)
-                        .unwrap();
-                    thread::sleep(sleep_duration);
+                    scan_escape(&mut psp, &command, sleep_duration);
+                    continue;
                 }
 
                 psp.send_command(&command, &mut buffer).unwrap();
 
-                if escape {
-                    psp.send_command(CMD_EXIT_ESCAPE_MODE, &mut _dummy).unwrap();
-                }
-
                 print!("Command {:02x}: ", j);
 
-                let mut found = false;
-                for k in 2..buffer.len() {
-                    if buffer[k] != 0xff {
-                        found = true;
-                    }
-                }
-                if !found {
+                if buffer[2..].iter().all(|x| *x == 0xff) {
                     print!("\r");
                     continue;
                 }
@@ -104,10 +91,48 @@ fn main() {
                     print!("{:02x} ", item);
                 }
 
-                println!("");
+                println!();
             }
         }
 
         println!("                                         ");
     }
 }
+
+/// Try a command in escape mode. Every command gets a session of its own so
+/// whatever it does, the controller is let back out of escape mode afterwards.
+fn scan_escape(
+    psp: &mut PlayStationPort<SpidevBus, CdevPin>,
+    command: &[u8],
+    sleep_duration: time::Duration,
+) {
+    let mut session = match psp.config_session() {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    thread::sleep(sleep_duration);
+
+    print!("Command {:02x}: ", command[1]);
+
+    let reply = match session.send(command) {
+        Ok(x) => x,
+        Err(_) => {
+            print!("\r");
+            return;
+        }
+    };
+
+    for item in reply.iter() {
+        print!("{:02x} ", item);
+    }
+
+    // Show what the ones we know about mean
+    let mut arguments = [0u8; 6];
+    arguments.copy_from_slice(&command[3..9]);
+
+    match Command::from_bytes(command[1], arguments) {
+        Command::Other { .. } => println!(),
+        x => println!("{:?}", x.parse(reply)),
+    }
+}
@@ -3,8 +3,13 @@
 //! A musical baton controller with accelerometers for playing musical conductor
 //! games such as Mad Maestro
 
+use super::decoder::Decoder;
+use super::deviceid::DeviceId;
+use super::{check_length, ControllerData, DecodeError, Device};
+use byteorder::{ByteOrder, LittleEndian};
+use core::convert::TryFrom;
+
 /// The two buttons found on the baton
-#[repr(C)]
 #[derive(Copy, Clone)]
 pub struct BatonButtons {
     data: u16,
@@ -14,6 +19,12 @@ impl BatonButtons {
     const B_A: u16 = 0x0008;
     const B_B: u16 = 0x2000;
 
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            data: LittleEndian::read_u16(bytes),
+        }
+    }
+
     /// A button on the controller
     pub fn a(&self) -> bool {
         self.data & Self::B_A == 0
@@ -25,7 +36,6 @@ impl BatonButtons {
     }
 }
 
-#[repr(C)]
 #[derive(Copy, Clone)]
 /// Represents the classic Controller
 pub struct Baton {
@@ -40,3 +50,34 @@ pub struct Baton {
     /// Unknown, assuming acceleration
     pub a: u8,
 }
+
+impl TryFrom<&[u8]> for Baton {
+    type Error = DecodeError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        check_length(data, 6)?;
+
+        Ok(Self {
+            buttons: BatonButtons::from_bytes(data),
+            z: data[2],
+            x: data[3],
+            y: data[4],
+            a: data[5],
+        })
+    }
+}
+
+/// Decodes the Mad Maestro baton. It answers with the neGcon's ID, so
+/// it isn't one of the built-in decoders. Add it to the port if you know
+/// that's what's plugged in.
+pub struct BatonDecoder;
+
+impl Decoder for BatonDecoder {
+    fn claims(&self, id: DeviceId, _: &ControllerData) -> bool {
+        id == DeviceId::NEGCON
+    }
+
+    fn decode(&self, _: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        Ok(Device::Baton(data.decode()?))
+    }
+}
@@ -3,10 +3,13 @@
 //! This is the predecessor of the DualShock and is the controller
 //! which originally shipped with the original PlayStation
 
-use super::HasStandardButtons;
+use super::decoder::Decoder;
+use super::deviceid::DeviceId;
+use super::{check_length, ControllerData, DecodeError, Device, HasStandardButtons};
+use byteorder::{ByteOrder, LittleEndian};
+use core::convert::TryFrom;
 
 /// The digital buttons of the gamepad
-#[repr(C)]
 #[derive(Copy, Clone)]
 pub struct GamepadButtons {
     data: u16,
@@ -37,6 +40,12 @@ impl GamepadButtons {
     const PS_CROSS: u16 = 0x4000;
     const PS_SQUARE: u16 = 0x8000;
 
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            data: LittleEndian::read_u16(bytes),
+        }
+    }
+
     /// A button on the controller
     pub fn select(&self) -> bool {
         self.data & Self::PS_SELECT == 0
@@ -124,7 +133,6 @@ impl GamepadButtons {
     }
 }
 
-#[repr(C)]
 #[derive(Copy, Clone)]
 /// Represents the classic Controller
 pub struct Classic {
@@ -132,8 +140,36 @@ pub struct Classic {
     pub buttons: GamepadButtons,
 }
 
+impl TryFrom<&[u8]> for Classic {
+    type Error = DecodeError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        check_length(data, 2)?;
+
+        Ok(Self {
+            buttons: GamepadButtons::from_bytes(data),
+        })
+    }
+}
+
 impl HasStandardButtons for Classic {
     fn buttons(&self) -> GamepadButtons {
         self.buttons
     }
 }
+
+/// Decodes the original Controller, and anything else in digital mode
+pub struct ClassicDecoder;
+
+impl Decoder for ClassicDecoder {
+    fn claims(&self, id: DeviceId, _: &ControllerData) -> bool {
+        id == DeviceId::CONTROLLER || id == DeviceId::DIGITAL
+    }
+
+    fn decode(&self, id: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        Ok(match id {
+            DeviceId::DIGITAL => Device::Digital(data.decode()?),
+            _ => Device::Classic(data.decode()?),
+        })
+    }
+}
@@ -0,0 +1,241 @@
+//! Controller Commands
+//! ============================
+//! Everything past polling happens through a handful of commands starting at
+//! 0x40, most of which the controller only listens to in escape mode. Each is
+//! nine bytes on the wire: the usual three byte header, then six arguments, with
+//! six bytes of reply coming back the other way.
+//!
+//! Not all of them are understood yet. The ones that aren't come through as
+//! `Command::Other` and `Response::Raw` so they can still be poked at. Send them
+//! with `ConfigSession::command`.
+//!
+//! ```
+//! use pscontroller_rs::command::{Command, Response};
+//!
+//! let command = Command::ReadConstant1 { address: 1 };
+//! let mut packet = [0u8; 9];
+//! command.write(&mut packet);
+//! assert_eq!(packet, [0x00, 0x46, 0x00, 0x01, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a]);
+//!
+//! let reply = [0x00, 0x00, 0x01, 0x01, 0x01, 0x14];
+//! assert_eq!(
+//!     command.parse(&reply).unwrap(),
+//!     Response::Constant([0x00, 0x01, 0x01, 0x01, 0x14])
+//! );
+//! ```
+
+use super::{check_length, ControllerData, DecodeError, HEADER_LEN};
+
+/// Arguments every command takes
+pub const ARGUMENT_LENGTH: usize = 6;
+/// Bytes of a whole command, header included
+pub const COMMAND_LENGTH: usize = HEADER_LEN + ARGUMENT_LENGTH;
+
+/// Padding the controller expects in arguments that don't mean anything
+const FILLER: u8 = 0x5a;
+
+/// A command to send to the controller
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    /// 0x40: Turn pressure sensing on or off for one of the DualShock 2's twelve
+    /// pressure sensitive buttons
+    InitPressure {
+        /// Which button, in the order the pressures are reported
+        button: u8,
+        /// Whether to report its pressure
+        enable: bool,
+    },
+    /// 0x41: Ask which bytes the controller reports when polled
+    QueryFormat,
+    /// 0x42: Poll, just like outside of escape mode. Escape mode replies are
+    /// always three words long, so there's only room for buttons and two axes.
+    Poll {
+        /// Values for the motors, in the slots set up with `MapActuators`
+        actuators: [u8; ARGUMENT_LENGTH],
+    },
+    /// 0x43: Enter (`true`) or leave (`false`) escape mode. Sent outside escape
+    /// mode it doubles as a poll and comes back with the buttons.
+    Escape(bool),
+    /// 0x44: Pick between digital and analog mode
+    SetMode {
+        /// Analog mode, with the LED lit
+        analog: bool,
+        /// Stop the Analog button from changing the mode
+        locked: bool,
+    },
+    /// 0x45: Read the extended status
+    ReadStatus,
+    /// 0x46: Read half of the first constant table
+    ReadConstant1 {
+        /// Which half, 0 or 1
+        address: u8,
+    },
+    /// 0x47: Read the second constant table
+    ReadConstant2 {
+        /// Always 0 as far as anyone's seen
+        address: u8,
+    },
+    /// 0x4C: Read half of the third constant table
+    ReadConstant3 {
+        /// Which half, 0 or 1
+        address: u8,
+    },
+    /// 0x4D: Choose which bytes of a poll drive which motor. `0xFF` leaves a
+    /// byte unmapped.
+    MapActuators([u8; ARGUMENT_LENGTH]),
+    /// 0x4F: Choose which bytes the controller reports when polled, one bit
+    /// per byte starting from the low bit of the first
+    SetResponseFormat([u8; 3]),
+    /// 0xA0: Something only the DualShock 2 answers. Nobody knows what yet.
+    ReadExtra,
+    /// Anything else, sent as is. 0x48 to 0x4B and 0x4E are acknowledged by
+    /// most controllers but don't seem to do anything.
+    Other {
+        /// The command byte
+        command: u8,
+        /// Its arguments
+        arguments: [u8; ARGUMENT_LENGTH],
+    },
+}
+
+/// What came back from a command
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Response {
+    /// The controller took the command and had nothing to say about it
+    Done,
+    /// Which bytes the controller reports when polled, in the same layout as
+    /// `Command::SetResponseFormat`
+    Format([u8; 3]),
+    /// Buttons and whatever else the controller sends when polled
+    Input(ControllerData),
+    /// The extended status. Byte 0 tells the DualShock (1) from the DualShock 2 (3),
+    /// byte 2 says whether the analog LED is on.
+    Status([u8; ARGUMENT_LENGTH]),
+    /// One of the constant tables
+    Constant([u8; 5]),
+    /// The actuator mapping from before this one replaced it
+    Actuators([u8; ARGUMENT_LENGTH]),
+    /// A reply we don't know how to read yet
+    Raw([u8; ARGUMENT_LENGTH]),
+}
+
+impl Command {
+    /// Work out which command some bytes are meant to be. Anything not
+    /// recognised becomes `Command::Other`.
+    pub fn from_bytes(command: u8, arguments: [u8; ARGUMENT_LENGTH]) -> Self {
+        match command {
+            0x40 => Command::InitPressure {
+                button: arguments[0],
+                enable: arguments[1] == 0x02,
+            },
+            0x41 => Command::QueryFormat,
+            0x42 => Command::Poll {
+                actuators: arguments,
+            },
+            0x43 => Command::Escape(arguments[0] == 0x01),
+            0x44 => Command::SetMode {
+                analog: arguments[0] == 0x01,
+                locked: arguments[1] == 0x03,
+            },
+            0x45 => Command::ReadStatus,
+            0x46 => Command::ReadConstant1 {
+                address: arguments[0],
+            },
+            0x47 => Command::ReadConstant2 {
+                address: arguments[0],
+            },
+            0x4c => Command::ReadConstant3 {
+                address: arguments[0],
+            },
+            0x4d => Command::MapActuators(arguments),
+            0x4f => Command::SetResponseFormat([arguments[0], arguments[1], arguments[2]]),
+            0xa0 => Command::ReadExtra,
+            _ => Command::Other { command, arguments },
+        }
+    }
+
+    /// The command byte
+    pub fn code(&self) -> u8 {
+        match self {
+            Command::InitPressure { .. } => 0x40,
+            Command::QueryFormat => 0x41,
+            Command::Poll { .. } => 0x42,
+            Command::Escape(_) => 0x43,
+            Command::SetMode { .. } => 0x44,
+            Command::ReadStatus => 0x45,
+            Command::ReadConstant1 { .. } => 0x46,
+            Command::ReadConstant2 { .. } => 0x47,
+            Command::ReadConstant3 { .. } => 0x4c,
+            Command::MapActuators(_) => 0x4d,
+            Command::SetResponseFormat(_) => 0x4f,
+            Command::ReadExtra => 0xa0,
+            Command::Other { command, .. } => *command,
+        }
+    }
+
+    /// The six bytes that follow the header
+    pub fn arguments(&self) -> [u8; ARGUMENT_LENGTH] {
+        match *self {
+            Command::InitPressure { button, enable } => {
+                [button, if enable { 0x02 } else { 0x00 }, 0, 0, 0, 0]
+            }
+            Command::QueryFormat => [FILLER; ARGUMENT_LENGTH],
+            Command::Poll { actuators } => actuators,
+            Command::Escape(enter) => [enter as u8, 0, 0, 0, 0, 0],
+            Command::SetMode { analog, locked } => {
+                [analog as u8, if locked { 0x03 } else { 0x00 }, 0, 0, 0, 0]
+            }
+            Command::ReadStatus => [FILLER; ARGUMENT_LENGTH],
+            Command::ReadConstant1 { address }
+            | Command::ReadConstant2 { address }
+            | Command::ReadConstant3 { address } => {
+                [address, FILLER, FILLER, FILLER, FILLER, FILLER]
+            }
+            Command::MapActuators(mapping) => mapping,
+            Command::SetResponseFormat(mask) => [mask[0], mask[1], mask[2], 0, 0, 0],
+            Command::ReadExtra => [0x00; ARGUMENT_LENGTH],
+            Command::Other { arguments, .. } => arguments,
+        }
+    }
+
+    /// Write the command into the start of a packet, header and all. Anything
+    /// past the arguments is left alone, and a packet too short for all of
+    /// them only gets the ones that fit.
+    pub fn write(&self, packet: &mut [u8]) {
+        let mut command = [0u8; COMMAND_LENGTH];
+
+        command[1] = self.code();
+        command[HEADER_LEN..].copy_from_slice(&self.arguments());
+
+        let length = packet.len().min(COMMAND_LENGTH);
+        packet[..length].copy_from_slice(&command[..length]);
+    }
+
+    /// Make sense of what came back after the header. Polls keep however much
+    /// data there was, everything else needs the full six bytes.
+    pub fn parse(&self, reply: &[u8]) -> Result<Response, DecodeError> {
+        if let Command::Poll { .. } | Command::Escape(_) = self {
+            return Ok(Response::Input(ControllerData::new(reply)));
+        }
+
+        check_length(reply, ARGUMENT_LENGTH)?;
+
+        let mut data = [0u8; ARGUMENT_LENGTH];
+        data.copy_from_slice(&reply[..ARGUMENT_LENGTH]);
+
+        Ok(match self {
+            Command::QueryFormat => Response::Format([data[0], data[1], data[2]]),
+            Command::ReadStatus => Response::Status(data),
+            Command::ReadConstant1 { .. }
+            | Command::ReadConstant2 { .. }
+            | Command::ReadConstant3 { .. } => {
+                let mut constant = [0u8; 5];
+                constant.copy_from_slice(&data[1..]);
+                Response::Constant(constant)
+            }
+            Command::MapActuators(_) => Response::Actuators(data),
+            Command::ReadExtra | Command::Other { .. } => Response::Raw(data),
+            _ => Response::Done,
+        })
+    }
+}
@@ -0,0 +1,520 @@
+//! Configuration Sessions
+//! ============================
+//! Most of what makes a controller interesting is set up in escape mode, where
+//! it answers with an ID of `0xF3` and takes the 0x40 to 0x4F family of commands.
+//! Leaving a controller there by accident means it stops reporting buttons, so
+//! a `ConfigSession` always sends the exit command when it goes away, whether
+//! that's from `exit`, an error bailing out early or just falling out of scope.
+//!
+//! ```
+//! # #[cfg(feature = "simulator")] {
+//! use pscontroller_rs::simulator::{Personality, Simulator};
+//! use pscontroller_rs::PlayStationPort;
+//!
+//! let sim = Simulator::new(Personality::DualShock);
+//! let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+//!
+//! let mut session = port.config_session().unwrap();
+//! // Analog mode, with the Analog button locked so players can't leave it
+//! session.set_mode(true, true).unwrap();
+//! session.exit().unwrap();
+//!
+//! assert!(sim.is_analog() && sim.is_locked());
+//! # }
+//! ```
+
+use super::command::{Command, Response, ARGUMENT_LENGTH, COMMAND_LENGTH};
+use super::deviceid::{DeviceId, DeviceType};
+use super::dualshock::{PressureButton, ResponseFormat};
+use super::fingerprint::Model;
+use super::{
+    ControllerConfiguration, ControllerData, Error, PlayStationPort, HEADER_LEN, POLL_MAX_LENGTH,
+};
+use core::fmt;
+use hal::digital::OutputPin;
+use hal::spi::SpiBus;
+
+/// What a slot of the actuator map holds when it doesn't drive anything
+const UNMAPPED: u8 = 0xff;
+
+/// A controller held in escape mode. Get one with `PlayStationPort::config_session`.
+pub struct ConfigSession<'a, SPI, CS>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+{
+    port: &'a mut PlayStationPort<SPI, CS>,
+    buffer: [u8; POLL_MAX_LENGTH],
+    id: DeviceId,
+    input: ControllerData,
+    open: bool,
+}
+
+impl<'a, SPI, CS> ConfigSession<'a, SPI, CS>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+{
+    /// Wake up the controller if needed and put it into escape mode
+    pub(crate) fn new(port: &'a mut PlayStationPort<SPI, CS>) -> Result<Self, Error<SPI::Error>> {
+        let mut buffer = [0u8; POLL_MAX_LENGTH];
+
+        port.transact(super::CMD_POLL, &mut buffer)?;
+        let polled = buffer[1];
+
+        let mut session = Self {
+            port,
+            buffer,
+            id: DeviceId::NOT_PRESENT,
+            input: ControllerData::new(&[]),
+            open: true,
+        };
+
+        // Going into escape mode polls the controller too, so leave room for
+        // everything it might send back
+        let mut command = [0u8; POLL_MAX_LENGTH];
+        Command::Escape(true).write(&mut command);
+
+        // If the controller heard us but the reply got mangled it could be in
+        // escape mode anyway, so the session stays open to clean up after
+        // itself when it's dropped
+        match session.port.transact(&command, &mut session.buffer) {
+            Ok(()) => {
+                let length = PlayStationPort::<SPI, CS>::payload_length(&session.buffer);
+                session.id = DeviceId::new(session.buffer[1]);
+                session.input = ControllerData::new(&session.buffer[HEADER_LEN..][..length]);
+
+                Ok(session)
+            }
+            // Still answering with the ID it polled with, it just never
+            // acknowledged the command because it has no escape mode, so
+            // there's nothing to leave
+            Err(Error::BadResponse) if session.buffer[1] == polled => {
+                session.open = false;
+                Err(Error::Unsupported)
+            }
+            Err(x) => Err(x),
+        }
+    }
+
+    /// The ID the controller polled with just before it went into escape mode
+    pub fn id(&self) -> DeviceId {
+        self.id
+    }
+
+    /// What the controller was sending just before it went into escape mode
+    pub fn input(&self) -> &ControllerData {
+        &self.input
+    }
+
+    /// Send any command the controller understands in escape mode. Returns what
+    /// came back after the header, as long as the command. Anything shorter
+    /// than the header is a `BadLength`.
+    pub fn send(&mut self, command: &[u8]) -> Result<&[u8], Error<SPI::Error>> {
+        if command.len() < HEADER_LEN {
+            return Err(Error::BadLength);
+        }
+
+        let length = command.len().min(POLL_MAX_LENGTH);
+
+        self.port
+            .configure(&command[..length], &mut self.buffer[..length])?;
+
+        Ok(&self.buffer[HEADER_LEN..length])
+    }
+
+    /// Send a command and make sense of the reply. Leaving escape mode this
+    /// way is the same as `exit`, with nothing left to send when the session
+    /// is dropped.
+    pub fn command(&mut self, command: Command) -> Result<Response, Error<SPI::Error>> {
+        let reply = self.exchange(&command)?;
+
+        if command == Command::Escape(false) {
+            self.open = false;
+        }
+
+        command.parse(&reply).map_err(Error::Decode)
+    }
+
+    /// Send a command and hand back the reply as is
+    fn exchange(&mut self, command: &Command) -> Result<[u8; ARGUMENT_LENGTH], Error<SPI::Error>> {
+        let mut packet = [0u8; COMMAND_LENGTH];
+        let mut reply = [0u8; ARGUMENT_LENGTH];
+
+        command.write(&mut packet);
+        reply.copy_from_slice(self.send(&packet)?);
+
+        Ok(reply)
+    }
+
+    /// Pick between digital and analog mode (0x44), and whether the Analog
+    /// button on the controller is allowed to change it back
+    pub fn set_mode(&mut self, analog: bool, locked: bool) -> Result<(), Error<SPI::Error>> {
+        self.exchange(&Command::SetMode { analog, locked })?;
+
+        // Changing modes puts the response format back the way it started
+        self.port.format = ResponseFormat::ANALOG;
+        self.port.analog_lock = if analog { Some(locked) } else { None };
+
+        Ok(())
+    }
+
+    /// Read the controller's extended status (0x45)
+    pub fn read_status(&mut self) -> Result<[u8; 6], Error<SPI::Error>> {
+        self.exchange(&Command::ReadStatus)
+    }
+
+    /// Find out whether the controller is in analog mode. The Guitar Hero
+    /// controller never lights its LED, so an ID saying the controller was
+    /// in analog mode before going into escape mode counts too.
+    pub fn analog_mode(&mut self) -> Result<AnalogMode, Error<SPI::Error>> {
+        let status = self.read_status()?;
+
+        Ok(match (status[2], self.id.kind()) {
+            (0x01, _) | (_, DeviceType::Analog) => AnalogMode::Analog,
+            _ => AnalogMode::Digital,
+        })
+    }
+
+    /// Read one of the two halves of the first constant table (0x46)
+    pub fn read_constant1(&mut self, address: u8) -> Result<[u8; 5], Error<SPI::Error>> {
+        self.read_constant(Command::ReadConstant1 { address })
+    }
+
+    /// Read the second constant table (0x47)
+    pub fn read_constant2(&mut self) -> Result<[u8; 5], Error<SPI::Error>> {
+        self.read_constant(Command::ReadConstant2 { address: 0 })
+    }
+
+    /// Read one of the two halves of the third constant table (0x4C)
+    pub fn read_constant3(&mut self, address: u8) -> Result<[u8; 5], Error<SPI::Error>> {
+        self.read_constant(Command::ReadConstant3 { address })
+    }
+
+    fn read_constant(&mut self, command: Command) -> Result<[u8; 5], Error<SPI::Error>> {
+        let reply = self.exchange(&command)?;
+        let mut constant = [0u8; 5];
+
+        constant.copy_from_slice(&reply[1..]);
+
+        Ok(constant)
+    }
+
+    /// Read the status and every constant table
+    pub fn read_config(&mut self) -> Result<ControllerConfiguration, Error<SPI::Error>> {
+        Ok(ControllerConfiguration {
+            status: self.read_status()?,
+            const1a: self.read_constant1(0)?,
+            const1b: self.read_constant1(1)?,
+            const2: self.read_constant2()?,
+            const3a: self.read_constant3(0)?,
+            const3b: self.read_constant3(1)?,
+        })
+    }
+
+    /// Choose which bytes of the poll command drive which motor (0x4D).
+    /// `PollCommand`s sent with `read_input` afterwards write into these slots.
+    pub fn map_actuators(&mut self, map: ActuatorMap) -> Result<(), Error<SPI::Error>> {
+        self.exchange(&Command::MapActuators(map.to_bytes()))?;
+        self.port.actuators = map;
+
+        Ok(())
+    }
+
+    /// Turn pressure sensing on or off for one of the DualShock 2's buttons
+    /// (0x40). Whether its pressure is sent is up to the response format.
+    pub fn set_pressure(
+        &mut self,
+        button: PressureButton,
+        enable: bool,
+    ) -> Result<(), Error<SPI::Error>> {
+        self.exchange(&Command::InitPressure {
+            button: button.index() as u8,
+            enable,
+        })?;
+
+        Ok(())
+    }
+
+    /// Ask which bytes the DualShock 2 is sending when polled (0x41). Only
+    /// works in analog mode, where it comes back as all zeros otherwise.
+    pub fn response_format(&mut self) -> Result<ResponseFormat, Error<SPI::Error>> {
+        let reply = self.exchange(&Command::QueryFormat)?;
+
+        Ok(ResponseFormat::from_bytes([reply[0], reply[1], reply[2]]))
+    }
+
+    /// Choose which bytes the DualShock 2 reports when polled (0x4F). The port
+    /// remembers it so `read_input` can make sense of them.
+    pub fn set_response_format(&mut self, format: ResponseFormat) -> Result<(), Error<SPI::Error>> {
+        self.exchange(&Command::SetResponseFormat(format.to_bytes()))?;
+        self.port.format = format;
+
+        Ok(())
+    }
+
+    /// Leave escape mode. Dropping the session does the same thing, but this
+    /// way you get to hear about it if the controller didn't go along with it.
+    pub fn exit(mut self) -> Result<(), Error<SPI::Error>> {
+        self.open = false;
+
+        self.exchange(&Command::Escape(false))?;
+
+        Ok(())
+    }
+}
+
+impl<'a, SPI, CS> Drop for ConfigSession<'a, SPI, CS>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+{
+    fn drop(&mut self) {
+        if self.open {
+            let _ = self.exchange(&Command::Escape(false));
+        }
+    }
+}
+
+/// The two modes a controller with an Analog button can be in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnalogMode {
+    /// Only buttons, like the original Controller. The LED is off.
+    Digital,
+    /// Buttons and sticks, with the LED lit
+    Analog,
+}
+
+/// What to do when a controller we put in analog mode drops back to digital
+/// mode by itself, which is what the Analog button does when it isn't locked
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnalogPolicy {
+    /// Carry on in digital mode. `read_input` starts returning the
+    /// controller as a `Device::Digital`.
+    Ignore,
+    /// Fail the next poll with `Error::ModeChanged`, then carry on in
+    /// digital mode
+    Report,
+    /// Put it straight back in analog mode, with the actuators, pressures and
+    /// response format it had before
+    Restore,
+}
+
+/// Which family of controller answered, going by the first status byte
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Generation {
+    /// The DualShock, and everything pretending to be one like the Guitar Hero
+    /// controller and JogCon
+    DualShock,
+    /// The DualShock 2
+    DualShock2,
+    /// Something else entirely
+    Other(u8),
+}
+
+/// What an actuator can do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActuatorKind {
+    /// Only on or off, like the DualShock's small motor
+    OnOff,
+    /// Driven at a strength from 0 to 255, like the DualShock's big motor
+    Variable,
+    /// Some other value we haven't seen yet
+    Other(u8),
+}
+
+/// One of the actuators described by the 0x46 table
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Actuator {
+    /// What it can do
+    pub kind: ActuatorKind,
+    /// How much current it draws, relatively speaking. The DualShock's small
+    /// motor says 10 and the big one 20.
+    pub current: u8,
+}
+
+/// Which bytes of the poll command drive which actuator, as set with 0x4D.
+/// Each of the six slots after the poll header holds the index of the actuator
+/// it drives, or `0xFF` if it doesn't drive anything.
+///
+/// ```
+/// use pscontroller_rs::config::ActuatorMap;
+///
+/// // The big motor in the first slot and the small one in the fourth
+/// let map = ActuatorMap::NONE.with(0, 1).with(3, 0);
+/// assert_eq!(map.to_bytes(), [0x01, 0xff, 0xff, 0x00, 0xff, 0xff]);
+/// assert_eq!(map.slot(0), Some(3));
+/// assert_eq!(map.slot(2), None);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ActuatorMap {
+    slots: [u8; ARGUMENT_LENGTH],
+}
+
+impl ActuatorMap {
+    /// Nothing drives any of the actuators, so there's no rumble at all
+    pub const NONE: Self = Self {
+        slots: [UNMAPPED; ARGUMENT_LENGTH],
+    };
+    /// The DualShock's small motor in the first slot and the big one in the second
+    pub const DUALSHOCK: Self = Self {
+        slots: [0x00, 0x01, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED],
+    };
+    /// The JogCon's motor in the first slot
+    pub const JOGCON: Self = Self {
+        slots: [0x00, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED],
+    };
+
+    /// Use the bytes sent with 0x4D as is
+    pub const fn from_bytes(slots: [u8; ARGUMENT_LENGTH]) -> Self {
+        Self { slots }
+    }
+
+    /// Give every actuator the controller says it has a slot of its own, in
+    /// order. Handy for controllers that don't lay out their motors like the
+    /// DualShock does.
+    pub fn from_config(config: &ControllerConfiguration) -> Self {
+        let count = usize::from(config.actuator_count()).min(ARGUMENT_LENGTH);
+
+        (0..count).fold(Self::NONE, |map, x| map.with(x, x as u8))
+    }
+
+    /// The bytes to send with 0x4D
+    pub const fn to_bytes(&self) -> [u8; ARGUMENT_LENGTH] {
+        self.slots
+    }
+
+    /// Drive an actuator from a slot, taking over whatever it drove before.
+    /// Slots past the sixth are ignored.
+    pub fn with(mut self, slot: usize, actuator: u8) -> Self {
+        if let Some(x) = self.slots.get_mut(slot) {
+            *x = actuator;
+        }
+
+        self
+    }
+
+    /// Stop a slot from driving anything
+    pub fn without(self, slot: usize) -> Self {
+        self.with(slot, UNMAPPED)
+    }
+
+    /// The actuator a slot drives, if any
+    pub fn actuator(&self, slot: usize) -> Option<u8> {
+        match self.slots.get(slot) {
+            Some(&UNMAPPED) | None => None,
+            Some(&x) => Some(x),
+        }
+    }
+
+    /// The first slot that drives an actuator, if any
+    pub fn slot(&self, actuator: u8) -> Option<usize> {
+        if actuator == UNMAPPED {
+            return None;
+        }
+
+        self.slots.iter().position(|&x| x == actuator)
+    }
+}
+
+impl Default for ActuatorMap {
+    fn default() -> Self {
+        Self::DUALSHOCK
+    }
+}
+
+impl ControllerConfiguration {
+    /// Which family of controller this is
+    pub fn generation(&self) -> Generation {
+        match self.status[0] {
+            0x01 => Generation::DualShock,
+            0x03 => Generation::DualShock2,
+            x => Generation::Other(x),
+        }
+    }
+
+    /// Whether the controller was in analog mode with its LED lit. The Guitar
+    /// Hero controller never says it is, even though it always is.
+    pub fn analog(&self) -> bool {
+        self.status[2] == 0x01
+    }
+
+    /// The controller, as far as its status and constants can tell. The JogCon
+    /// and Guitar Hero controller both say they're a DualShock, but give
+    /// themselves away with their first actuator and their second mode.
+    pub fn model(&self) -> Option<Model> {
+        match (self.generation(), self.const1a, self.mode_types()[1]) {
+            (Generation::DualShock2, _, _) => Some(Model::DualShock2),
+            (Generation::DualShock, [0x00, 0x04, 0x03, 0x01, 0x1e], _) => Some(Model::JogCon),
+            (Generation::DualShock, _, 0x7) => Some(Model::GuitarHero),
+            (Generation::DualShock, _, _) => Some(Model::DualShock),
+            (Generation::Other(_), _, _) => None,
+        }
+    }
+
+    /// How many modes the controller can switch between, counting the ones it
+    /// reports through 0x4C
+    pub fn mode_count(&self) -> u8 {
+        self.mode_types().iter().filter(|x| **x != 0).count() as u8
+    }
+
+    /// The device type (the high nybble of the ID) of each mode the controller
+    /// has, when it's asked for them through 0x4C
+    pub fn mode_types(&self) -> [u8; 2] {
+        [self.const3a[2], self.const3b[2]]
+    }
+
+    /// How many actuators the controller has
+    pub fn actuator_count(&self) -> u8 {
+        self.const2[1]
+    }
+
+    /// Details about one of the first two actuators, if the controller has it
+    pub fn actuator(&self, index: u8) -> Option<Actuator> {
+        let table = match index {
+            0 => &self.const1a,
+            1 => &self.const1b,
+            _ => return None,
+        };
+
+        if index >= self.actuator_count() {
+            return None;
+        }
+
+        Some(Actuator {
+            kind: match table[3] {
+                0x00 => ActuatorKind::OnOff,
+                0x01 => ActuatorKind::Variable,
+                x => ActuatorKind::Other(x),
+            },
+            current: table[4],
+        })
+    }
+}
+
+/// Something like "DualShock 2, analog on, 2 motors"
+impl fmt::Display for ControllerConfiguration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.model() {
+            Some(Model::DualShock2) => write!(f, "DualShock 2")?,
+            Some(Model::JogCon) => write!(f, "JogCon")?,
+            Some(Model::GuitarHero) => write!(f, "Guitar Hero")?,
+            Some(_) => write!(f, "DualShock")?,
+            None => write!(f, "Unknown ({:02x})", self.status[0])?,
+        }
+
+        let analog = if self.analog() { "on" } else { "off" };
+        let motors = match self.actuator_count() {
+            1 => "motor",
+            _ => "motors",
+        };
+
+        write!(
+            f,
+            ", analog {}, {} {}",
+            analog,
+            self.actuator_count(),
+            motors
+        )
+    }
+}
@@ -0,0 +1,152 @@
+//! Decoders
+//! ============================
+//! `read_input` polls the controller, then hands the ID and the rest of the
+//! poll to whichever `Decoder` claims them. The devices this library knows
+//! about each have one (`mouse::MouseDecoder`, `negcon::NegConDecoder` and so
+//! on), and you can add your own for hardware it doesn't know with
+//! `PlayStationPort::add_decoder`. Those get the first look, so they can also
+//! take over an ID from a built-in one, like the Guitar Hero controller
+//! answering as a DualShock. Since they see the data too, they can be as picky
+//! as they need to be about what they take.
+//!
+//! Your own devices come back as `Device::Custom`. The easiest way to get one
+//! is with a `Custom` decoder and a type that decodes from the poll's bytes,
+//! which the same decoder hands back with `Custom::get`.
+//!
+//! ```
+//! # #[cfg(feature = "simulator")] {
+//! use core::convert::TryFrom;
+//! use pscontroller_rs::decoder::Custom;
+//! use pscontroller_rs::deviceid::DeviceId;
+//! use pscontroller_rs::simulator::{Personality, Simulator};
+//! use pscontroller_rs::{DecodeError, PlayStationPort};
+//!
+//! // A pad that's only a single row of buttons
+//! struct Buttons(u16);
+//!
+//! impl TryFrom<&[u8]> for Buttons {
+//!     type Error = DecodeError;
+//!
+//!     fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+//!         match data {
+//!             [a, b, ..] => Ok(Buttons(u16::from_le_bytes([*a, *b]))),
+//!             _ => Err(DecodeError { expected: 2, actual: data.len() }),
+//!         }
+//!     }
+//! }
+//!
+//! static BUTTONS: Custom<Buttons> = Custom::new(1, &[DeviceId::CONTROLLER]);
+//!
+//! let sim = Simulator::new(Personality::Classic);
+//! let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+//! port.add_decoder(&BUTTONS).unwrap();
+//!
+//! let device = port.read_input(None).unwrap();
+//! let buttons = BUTTONS.get(&device).unwrap().unwrap();
+//! assert_eq!(buttons.0, 0xffff);
+//! # }
+//! ```
+
+use super::classic::ClassicDecoder;
+use super::deviceid::DeviceId;
+use super::dualshock::{DualShock2Decoder, DualShockDecoder};
+use super::guncon::GunConDecoder;
+use super::jogcon::JogConDecoder;
+use super::mouse::MouseDecoder;
+use super::negcon::NegConDecoder;
+use super::{ControllerData, DecodeError, Device};
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+
+/// How many decoders of your own a port can hold
+pub const MAX_DECODERS: usize = 8;
+
+/// The decoders `read_input` falls back on, for every device it can tell
+/// apart by ID. `BatonDecoder` and `GuitarHeroDecoder` aren't here, since
+/// their IDs belong to other devices, but you can add them yourself if you
+/// know that's what's plugged in.
+pub const BUILTIN: &[&(dyn Decoder + Sync)] = &[
+    &MouseDecoder,
+    &ClassicDecoder,
+    &DualShockDecoder,
+    &DualShock2Decoder,
+    &JogConDecoder,
+    &NegConDecoder,
+    &GunConDecoder,
+];
+
+/// Something that can make sense of a poll
+pub trait Decoder {
+    /// Whether a device answering with this ID and data is one this decoder
+    /// knows
+    fn claims(&self, id: DeviceId, data: &ControllerData) -> bool;
+
+    /// Turn what the device sent after the header into a `Device`
+    fn decode(&self, id: DeviceId, data: &ControllerData) -> Result<Device, DecodeError>;
+}
+
+/// A device decoded by a decoder of your own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CustomDevice {
+    /// Whatever number the decoder gave it, to tell your devices apart
+    pub tag: u16,
+    /// The ID it answered with
+    pub id: DeviceId,
+    /// Everything it sent after the header
+    pub data: ControllerData,
+}
+
+impl CustomDevice {
+    /// Decode the data as your own type
+    pub fn decode<'a, T>(&'a self) -> Result<T, DecodeError>
+    where
+        T: TryFrom<&'a [u8], Error = DecodeError>,
+    {
+        self.data.decode()
+    }
+}
+
+/// A decoder for a type of your own. It claims a list of IDs and passes them on
+/// as a `Device::Custom` with its tag, for `get` to turn into a `T`.
+pub struct Custom<T> {
+    tag: u16,
+    ids: &'static [DeviceId],
+    device: PhantomData<fn() -> T>,
+}
+
+impl<T> Custom<T> {
+    /// Decode devices answering with any of `ids` as `T`, tagged with `tag`
+    pub const fn new(tag: u16, ids: &'static [DeviceId]) -> Self {
+        Self {
+            tag,
+            ids,
+            device: PhantomData,
+        }
+    }
+
+    /// Decode a device this decoder passed on as your own type. `None` if it
+    /// came from anywhere else.
+    pub fn get<'a>(&self, device: &'a Device) -> Option<Result<T, DecodeError>>
+    where
+        T: TryFrom<&'a [u8], Error = DecodeError>,
+    {
+        match device {
+            Device::Custom(x) if x.tag == self.tag => Some(x.decode()),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Decoder for Custom<T> {
+    fn claims(&self, id: DeviceId, _: &ControllerData) -> bool {
+        self.ids.contains(&id)
+    }
+
+    fn decode(&self, id: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        Ok(Device::Custom(CustomDevice {
+            tag: self.tag,
+            id,
+            data: *data,
+        }))
+    }
+}
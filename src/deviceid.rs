@@ -0,0 +1,154 @@
+//! Device IDs
+//! ============================
+//! The second byte back from every poll says what's plugged in. The high nybble
+//! is the type of device and the low nybble how many 16bit words it's about to
+//! send. A lot of controllers share a type (see `research/README.md`), and
+//! the same controller can change IDs as it changes modes, so a DualShock
+//! answers 0x41 in digital mode and 0x73 in analog mode.
+//!
+//! ```
+//! use pscontroller_rs::deviceid::{DeviceId, DeviceType};
+//!
+//! let id = DeviceId::new(0x79);
+//! assert_eq!(id, DeviceId::DUALSHOCK2);
+//! assert_eq!(id.kind(), DeviceType::Analog);
+//! assert_eq!(id.words(), 9);
+//! assert_eq!(id.length(), 18);
+//! ```
+
+use core::fmt;
+
+/// The kind of device, going by the high nybble of its ID
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceType {
+    /// 0x1: The PlayStation Mouse
+    Mouse,
+    /// 0x2: The neGcon, and everything that borrowed its ID like steering
+    /// wheels and the Mad Maestro baton
+    NegCon,
+    /// 0x3: Konami's lightgun
+    KonamiLightgun,
+    /// 0x4: Buttons only. Most controllers with an Analog button use this in
+    /// digital mode, along with the DVD remote.
+    Digital,
+    /// 0x5: The Analog Joystick, and the Analog Controller (SCPH-1180) in its
+    /// green mode
+    AnalogGreen,
+    /// 0x6: Namco's GunCon and G-con45
+    GunCon,
+    /// 0x7: The DualShock and DualShock 2 in analog mode, the Analog
+    /// Controller in its red mode and the Guitar Hero controller
+    Analog,
+    /// 0x8: The multitap itself
+    Multitap,
+    /// 0x9: The keyboard and mouse adapter
+    Keyboard,
+    /// 0xC: The original Controller
+    Controller,
+    /// 0xE: The JogCon
+    JogCon,
+    /// 0xF: A controller in escape mode, or nothing at all when the whole ID
+    /// is 0xFF
+    Escape,
+    /// A type nobody's seen yet
+    Other(u8),
+}
+
+/// The ID a device answers a poll with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceId(u8);
+
+impl DeviceId {
+    /// Nothing plugged in, the bus just reads high
+    pub const NOT_PRESENT: Self = Self(0xff);
+    /// PlayStation mouse, SCPH-1030
+    pub const MOUSE: Self = Self(0x12);
+    /// Namco neGcon
+    pub const NEGCON: Self = Self(0x23);
+    /// Any controller with buttons and nothing else, like a DualShock in
+    /// digital mode
+    pub const DIGITAL: Self = Self(0x41);
+    /// Analog Joystick, SCPH-1110, or the Analog Controller in green mode
+    pub const ANALOG_GREEN: Self = Self(0x53);
+    /// Namco GunCon
+    pub const GUNCON: Self = Self(0x63);
+    /// DualShock in analog mode
+    pub const DUALSHOCK: Self = Self(0x73);
+    /// DualShock 2 in analog mode, sending every pressure
+    pub const DUALSHOCK2: Self = Self(0x79);
+    /// The multitap on its own, when the port behind it is empty
+    pub const MULTITAP: Self = Self(0x80);
+    /// Original controller, SCPH-1080
+    pub const CONTROLLER: Self = Self(0xc1);
+    /// Namco JogCon
+    pub const JOGCON: Self = Self(0xe3);
+    /// Any controller in escape mode
+    pub const CONFIGURATION: Self = Self(0xf3);
+
+    /// Wrap an ID byte
+    pub const fn new(id: u8) -> Self {
+        Self(id)
+    }
+
+    /// The ID byte as it came off the bus
+    pub const fn raw(self) -> u8 {
+        self.0
+    }
+
+    /// The kind of device
+    pub fn kind(self) -> DeviceType {
+        match self.0 >> 4 {
+            0x1 => DeviceType::Mouse,
+            0x2 => DeviceType::NegCon,
+            0x3 => DeviceType::KonamiLightgun,
+            0x4 => DeviceType::Digital,
+            0x5 => DeviceType::AnalogGreen,
+            0x6 => DeviceType::GunCon,
+            0x7 => DeviceType::Analog,
+            0x8 => DeviceType::Multitap,
+            0x9 => DeviceType::Keyboard,
+            0xc => DeviceType::Controller,
+            0xe => DeviceType::JogCon,
+            0xf => DeviceType::Escape,
+            x => DeviceType::Other(x),
+        }
+    }
+
+    /// How many 16bit words the device sends after the header. A low nybble
+    /// of 0 means the most there can be, 16.
+    pub const fn words(self) -> u8 {
+        match self.0 & 0x0f {
+            0 => 16,
+            x => x,
+        }
+    }
+
+    /// How many bytes the device sends after the header
+    pub const fn length(self) -> usize {
+        self.words() as usize * 2
+    }
+
+    /// Whether anything answered at all
+    pub fn present(self) -> bool {
+        self != Self::NOT_PRESENT
+    }
+}
+
+impl From<u8> for DeviceId {
+    fn from(id: u8) -> Self {
+        Self(id)
+    }
+}
+
+impl From<DeviceId> for u8 {
+    fn from(id: DeviceId) -> Self {
+        id.0
+    }
+}
+
+/// The ID in hex, like "0x73"
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#04x}", self.0)
+    }
+}
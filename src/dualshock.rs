@@ -6,10 +6,13 @@
 //! This also maps for the the Dual Analog (precursor to the Dual Shock) and
 //! the Analog controller (flight stick) as they both have the same buttons
 
-use super::{HasStandardButtons, PollCommand};
+use super::config::ActuatorMap;
+use super::decoder::Decoder;
+use super::deviceid::DeviceId;
+use super::{check_length, ControllerData, DecodeError, Device, HasStandardButtons, PollCommand};
 use crate::classic::GamepadButtons;
+use core::convert::TryFrom;
 
-#[repr(C)]
 #[derive(Copy, Clone)]
 /// Represents the DualShock 1 controller
 pub struct DualShock {
@@ -26,13 +29,199 @@ pub struct DualShock {
     pub ly: u8,
 }
 
+impl TryFrom<&[u8]> for DualShock {
+    type Error = DecodeError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        check_length(data, 6)?;
+
+        Ok(Self {
+            buttons: GamepadButtons::from_bytes(data),
+            rx: data[2],
+            ry: data[3],
+            lx: data[4],
+            ly: data[5],
+        })
+    }
+}
+
 impl HasStandardButtons for DualShock {
     fn buttons(&self) -> GamepadButtons {
         self.buttons
     }
 }
 
-#[repr(C)]
+/// The buttons on the DualShock 2 that know how hard they're pressed, in the
+/// order their pressures are sent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PressureButton {
+    /// D-pad right
+    Right,
+    /// D-pad left
+    Left,
+    /// D-pad up
+    Up,
+    /// D-pad down
+    Down,
+    /// Triangle
+    Triangle,
+    /// Circle
+    Circle,
+    /// Cross
+    Cross,
+    /// Square
+    Square,
+    /// L1
+    L1,
+    /// R1
+    R1,
+    /// L2
+    L2,
+    /// R2
+    R2,
+}
+
+impl PressureButton {
+    /// Every pressure button, in order
+    pub const ALL: [PressureButton; PRESSURE_COUNT] = [
+        PressureButton::Right,
+        PressureButton::Left,
+        PressureButton::Up,
+        PressureButton::Down,
+        PressureButton::Triangle,
+        PressureButton::Circle,
+        PressureButton::Cross,
+        PressureButton::Square,
+        PressureButton::L1,
+        PressureButton::R1,
+        PressureButton::L2,
+        PressureButton::R2,
+    ];
+
+    /// Where the button's pressure is among the twelve
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// How many buttons report pressure
+pub const PRESSURE_COUNT: usize = 12;
+
+/// The most the DualShock 2 can send: buttons, sticks and every pressure
+const FULL_LENGTH: usize = 6 + PRESSURE_COUNT;
+
+/// Which bytes the DualShock 2 sends when polled, set with command 0x4F. Each
+/// bit picks one byte of the full response: two of buttons, four of sticks
+/// and then the twelve pressures. Leaving things out makes polls shorter,
+/// which adds up at high poll rates.
+///
+/// ```
+/// use pscontroller_rs::dualshock::{PressureButton, ResponseFormat};
+///
+/// let format = ResponseFormat::ANALOG.with_pressure(PressureButton::Cross);
+/// assert_eq!(format.length(), 7);
+/// assert_eq!(format.to_bytes(), [0x3f, 0x10, 0x00]);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResponseFormat {
+    mask: u32,
+}
+
+impl ResponseFormat {
+    const BUTTONS: u32 = 0x0000_0003;
+    const STICKS: u32 = 0x0000_003c;
+    const PRESSURE_SHIFT: usize = 6;
+
+    /// Only the buttons
+    pub const DIGITAL: Self = Self {
+        mask: Self::BUTTONS,
+    };
+    /// Buttons and sticks, which is what analog mode starts out with
+    pub const ANALOG: Self = Self {
+        mask: Self::BUTTONS | Self::STICKS,
+    };
+    /// Everything, pressures and all
+    pub const ALL: Self = Self { mask: 0x0003_ffff };
+
+    /// Build a format from the bits sent with 0x4F. Bits past the eighteen
+    /// bytes the controller has are dropped.
+    pub fn from_bits(mask: u32) -> Self {
+        Self {
+            mask: mask & Self::ALL.mask,
+        }
+    }
+
+    /// The bits to send with 0x4F
+    pub fn bits(&self) -> u32 {
+        self.mask
+    }
+
+    /// The three bytes to send with 0x4F
+    pub fn to_bytes(&self) -> [u8; 3] {
+        [
+            self.mask as u8,
+            (self.mask >> 8) as u8,
+            (self.mask >> 16) as u8,
+        ]
+    }
+
+    /// Read the format back from the three bytes of 0x41 or 0x4F
+    pub fn from_bytes(bytes: [u8; 3]) -> Self {
+        Self::from_bits(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+    }
+
+    /// Also send both sticks
+    pub fn with_sticks(self) -> Self {
+        Self::from_bits(self.mask | Self::STICKS)
+    }
+
+    /// Leave out the sticks
+    pub fn without_sticks(self) -> Self {
+        Self::from_bits(self.mask & !Self::STICKS)
+    }
+
+    /// Also send how hard a button is pressed
+    pub fn with_pressure(self, button: PressureButton) -> Self {
+        Self::from_bits(self.mask | 1 << (Self::PRESSURE_SHIFT + button.index()))
+    }
+
+    /// Also send how hard each of some buttons is pressed
+    pub fn with_pressures(self, buttons: &[PressureButton]) -> Self {
+        buttons.iter().fold(self, |x, y| x.with_pressure(*y))
+    }
+
+    /// Leave out how hard a button is pressed
+    pub fn without_pressure(self, button: PressureButton) -> Self {
+        Self::from_bits(self.mask & !(1 << (Self::PRESSURE_SHIFT + button.index())))
+    }
+
+    /// Whether the buttons are sent
+    pub fn has_buttons(&self) -> bool {
+        self.mask & Self::BUTTONS == Self::BUTTONS
+    }
+
+    /// Whether the sticks are sent
+    pub fn has_sticks(&self) -> bool {
+        self.mask & Self::STICKS == Self::STICKS
+    }
+
+    /// Whether a button's pressure is sent
+    pub fn has_pressure(&self, button: PressureButton) -> bool {
+        self.mask & 1 << (Self::PRESSURE_SHIFT + button.index()) != 0
+    }
+
+    /// How many bytes the controller sends back
+    pub fn length(&self) -> usize {
+        self.mask.count_ones() as usize
+    }
+
+    /// How many 16 bit words the controller sends back, which ends up in the
+    /// low nybble of its ID
+    pub fn words(&self) -> u8 {
+        self.length().div_ceil(2) as u8
+    }
+}
+
 #[derive(Copy, Clone)]
 /// Represents the DualShock 2 controller
 pub struct DualShock2 {
@@ -48,9 +237,61 @@ pub struct DualShock2 {
     /// Left analog stick, up and down
     pub ly: u8,
 
-    /// List of possible pressure readings from the buttons
-    /// Note that these are configurable length
-    pub pressures: [u8; 8],
+    /// How hard each of the pressure buttons is pressed, in the order of
+    /// `PressureButton`. Any the controller didn't send are zero.
+    pub pressures: [u8; PRESSURE_COUNT],
+
+    /// The format the controller was sending in
+    pub format: ResponseFormat,
+}
+
+impl DualShock2 {
+    /// Decode a poll sent with a particular response format. Anything the
+    /// format leaves out reads as untouched: buttons up, sticks centred and
+    /// no pressure.
+    pub fn decode(data: &[u8], format: ResponseFormat) -> Result<Self, DecodeError> {
+        check_length(data, format.length())?;
+
+        let mut full = [0u8; FULL_LENGTH];
+        full[..6].copy_from_slice(&[0xff, 0xff, 0x80, 0x80, 0x80, 0x80]);
+
+        let sent = (0..FULL_LENGTH).filter(|x| format.bits() & 1 << x != 0);
+        for (byte, index) in data.iter().zip(sent) {
+            full[index] = *byte;
+        }
+
+        let mut pressures = [0u8; PRESSURE_COUNT];
+        pressures.copy_from_slice(&full[6..]);
+
+        Ok(Self {
+            buttons: GamepadButtons::from_bytes(&full),
+            rx: full[2],
+            ry: full[3],
+            lx: full[4],
+            ly: full[5],
+            pressures,
+            format,
+        })
+    }
+
+    /// How hard a button is pressed, from 0 to 255. `None` if the response
+    /// format left it out.
+    pub fn pressure(&self, button: PressureButton) -> Option<u8> {
+        if !self.format.has_pressure(button) {
+            return None;
+        }
+
+        Some(self.pressures[button.index()])
+    }
+}
+
+/// Decodes the full response, with every pressure
+impl TryFrom<&[u8]> for DualShock2 {
+    type Error = DecodeError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        Self::decode(data, ResponseFormat::ALL)
+    }
 }
 
 impl HasStandardButtons for DualShock2 {
@@ -80,7 +321,48 @@ impl ControlDS {
 impl PollCommand for ControlDS {
     /// Sets the command for the rumble motoros on the DualShock
     fn set_command(&self, command: &mut [u8]) {
-        command[0] = if self.little { 0xff } else { 0x00 };
-        command[1] = self.big;
+        self.set_mapped_command(command, &ActuatorMap::DUALSHOCK);
+    }
+
+    /// Sets the motors in whichever slots they were mapped to. The small motor
+    /// is actuator 0 and the big one actuator 1.
+    fn set_mapped_command(&self, command: &mut [u8], actuators: &ActuatorMap) {
+        if let Some(x) = actuators.slot(0) {
+            command[x] = if self.little { 0xff } else { 0x00 };
+        }
+        if let Some(x) = actuators.slot(1) {
+            command[x] = self.big;
+        }
+    }
+}
+
+/// Decodes the DualShock in analog mode, along with the Analog Joystick and
+/// the Analog Controller in green mode
+pub struct DualShockDecoder;
+
+impl Decoder for DualShockDecoder {
+    fn claims(&self, id: DeviceId, _: &ControllerData) -> bool {
+        id == DeviceId::DUALSHOCK || id == DeviceId::ANALOG_GREEN
+    }
+
+    fn decode(&self, id: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        Ok(match id {
+            DeviceId::ANALOG_GREEN => Device::AnalogGreen(data.decode()?),
+            _ => Device::DualShock(data.decode()?),
+        })
+    }
+}
+
+/// Decodes the DualShock 2 sending everything it has. Other response formats
+/// are decoded by the port, which knows which one it asked for.
+pub struct DualShock2Decoder;
+
+impl Decoder for DualShock2Decoder {
+    fn claims(&self, id: DeviceId, _: &ControllerData) -> bool {
+        id == DeviceId::DUALSHOCK2
+    }
+
+    fn decode(&self, _: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        Ok(Device::DualShock2(data.decode()?))
     }
 }
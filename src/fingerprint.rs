@@ -0,0 +1,529 @@
+//! Telling Controllers Apart
+//! ============================
+//! The ID byte only has sixteen device types to go around, so some controllers
+//! share one. The Guitar Hero controller says it's a DualShock and the Mad
+//! Maestro baton says it's a NeGcon. What they can't hide is how they behave:
+//! whether they have an escape mode, what their status and constants say,
+//! whether they take back the response format they report, how they answer a
+//! poll while in escape mode and what their input looks like when nobody's
+//! touching them.
+//!
+//! `PlayStationPort::observe` collects all of that, and a `Database` of
+//! `Fingerprint`s turns it into a best guess. The built in fingerprints come
+//! from `research/README.md`, and more can be added without touching the crate.
+//!
+//! ```
+//! # #[cfg(feature = "simulator")] {
+//! use pscontroller_rs::fingerprint::{Database, Model};
+//! use pscontroller_rs::simulator::{Personality, Simulator};
+//! use pscontroller_rs::PlayStationPort;
+//!
+//! let sim = Simulator::new(Personality::GuitarHero);
+//! let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+//!
+//! let guess = port.identify(&Database::new()).unwrap().unwrap();
+//! assert_eq!(guess.model, Model::GuitarHero);
+//! assert_eq!(guess.confidence, 100);
+//! # }
+//! ```
+
+use super::command::{Command, Response};
+use super::deviceid::DeviceId;
+use super::{ControllerConfiguration, ControllerData, DecodeError, Device, Error, PlayStationPort};
+use hal::digital::OutputPin;
+use hal::spi::SpiBus;
+
+/// The controllers we know how to recognise
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+    /// PlayStation Mouse (SCPH-1030)
+    Mouse,
+    /// Namco NeGcon, and the many wheels that copy it
+    NegCon,
+    /// Mad Maestro conductor baton
+    Baton,
+    /// Konami lightgun (untested)
+    KonamiLightgun,
+    /// The original digital Controller (SCPH-1080)
+    Controller,
+    /// Analog Joystick (SCPH-1110)
+    AnalogJoystick,
+    /// Dual Analog controller (SCPH-1180)
+    DualAnalog,
+    /// DualShock (SCPH-1200)
+    DualShock,
+    /// DualShock 2 (SCPH-10010)
+    DualShock2,
+    /// RedOctane Guitar Hero controller
+    GuitarHero,
+    /// Namco GunCon
+    GunCon,
+    /// Namco JogCon
+    JogCon,
+    /// PlayStation 2 DVD remote receiver (SCPH-10160)
+    DvdRemote,
+    /// Keyboard and mouse adapter (SCPH-2000)
+    KeyboardAdapter,
+    /// A multi-tap answering for itself
+    Multitap,
+    /// Something from a user's own fingerprint, numbered however they like
+    Custom(u16),
+}
+
+impl Model {
+    /// Decode a poll from this model. Models with a digital mode come back as
+    /// `Device::Digital` when that's all they sent, and models there isn't a
+    /// decoder for come back as `Device::Unknown` with the ID they polled with.
+    pub fn decode(&self, id: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        let digital = data.as_bytes().len() == 2;
+
+        Ok(match self {
+            Model::Mouse => Device::Mouse(data.decode()?),
+            Model::NegCon => Device::NegCon(data.decode()?),
+            Model::Baton => Device::Baton(data.decode()?),
+            Model::Controller => Device::Classic(data.decode()?),
+            Model::DvdRemote => Device::Digital(data.decode()?),
+            Model::AnalogJoystick
+            | Model::DualAnalog
+            | Model::DualShock
+            | Model::DualShock2
+            | Model::JogCon
+                if digital =>
+            {
+                Device::Digital(data.decode()?)
+            }
+            Model::AnalogJoystick => Device::AnalogGreen(data.decode()?),
+            Model::DualAnalog if id == DeviceId::ANALOG_GREEN => {
+                Device::AnalogGreen(data.decode()?)
+            }
+            Model::DualAnalog | Model::DualShock => Device::DualShock(data.decode()?),
+            Model::DualShock2 => Device::DualShock2(data.decode()?),
+            Model::GuitarHero => Device::GuitarHero(data.decode()?),
+            Model::GunCon => Device::GunCon(data.decode()?),
+            Model::JogCon => Device::JogCon(data.decode()?),
+            _ => Device::Unknown { id, data: *data },
+        })
+    }
+}
+
+/// Everything `PlayStationPort::observe` could find out about a controller
+#[derive(Clone, Copy, Debug)]
+pub struct Observation {
+    /// The ID from a poll
+    pub id: DeviceId,
+    /// What came back from that poll
+    pub data: ControllerData,
+    /// The status and constants, if the controller has an escape mode
+    pub config: Option<ControllerConfiguration>,
+    /// Whether the controller took back the response format it said it was
+    /// using (0x41 then 0x4F). Ones that don't say never get asked.
+    pub response_format: bool,
+    /// How the controller answered a poll while in escape mode
+    pub escape_poll: Option<[u8; 6]>,
+}
+
+/// How to recognise a model. Anything left as `None` doesn't count either way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fingerprint {
+    /// The model this describes
+    pub model: Model,
+    /// The ID it polls with
+    pub id: DeviceId,
+    /// Which bits of the ID have to match. `0xF0` ignores how much data it sends.
+    pub id_mask: u8,
+    /// Whether it has an escape mode
+    pub escape: Option<bool>,
+    /// The first byte of its status (0x45)
+    pub generation: Option<u8>,
+    /// The first half of its first constant table (0x46)
+    pub const1a: Option<[u8; 5]>,
+    /// The second half of its third constant table (0x4C)
+    pub const3b: Option<[u8; 5]>,
+    /// Whether it accepts a response format (0x4F)
+    pub response_format: Option<bool>,
+    /// How it answers a poll in escape mode
+    pub escape_poll: Option<[u8; 6]>,
+    /// What its input looks like when nobody's touching it. `None` bytes can be
+    /// anything. All of it has to match for it to count.
+    pub idle: [Option<u8>; 6],
+}
+
+impl Fingerprint {
+    /// A fingerprint that only goes by the ID byte, to build on with struct
+    /// update syntax
+    pub const fn new(model: Model, id: DeviceId) -> Self {
+        Self {
+            model,
+            id,
+            id_mask: 0xff,
+            escape: None,
+            generation: None,
+            const1a: None,
+            const3b: None,
+            response_format: None,
+            escape_poll: None,
+            idle: [None; 6],
+        }
+    }
+
+    /// How many of the things this fingerprint cares about were in the
+    /// observation, and how many it cares about in all. `None` if the ID is wrong.
+    fn score(&self, observation: &Observation) -> Option<(u8, u8)> {
+        if (observation.id.raw() ^ self.id.raw()) & self.id_mask != 0 {
+            return None;
+        }
+
+        let mut hits = 1;
+        let mut total = 1;
+        let mut check = |wanted: bool, matched: bool| {
+            if wanted {
+                total += 1;
+                hits += matched as u8;
+            }
+        };
+
+        let config = observation.config.as_ref();
+
+        check(self.escape.is_some(), self.escape == Some(config.is_some()));
+        check(
+            self.generation.is_some(),
+            self.generation.is_some() && self.generation == config.map(|x| x.status[0]),
+        );
+        check(
+            self.const1a.is_some(),
+            self.const1a.is_some() && self.const1a == config.map(|x| x.const1a),
+        );
+        check(
+            self.const3b.is_some(),
+            self.const3b.is_some() && self.const3b == config.map(|x| x.const3b),
+        );
+        check(
+            self.response_format.is_some(),
+            self.response_format == Some(observation.response_format),
+        );
+        check(
+            self.escape_poll.is_some(),
+            self.escape_poll.is_some() && self.escape_poll == observation.escape_poll,
+        );
+
+        let data = observation.data.as_bytes();
+        check(
+            self.idle.iter().any(|x| x.is_some()),
+            self.idle
+                .iter()
+                .enumerate()
+                .all(|(i, x)| x.is_none() || data.get(i) == x.as_ref()),
+        );
+
+        Some((hits, total))
+    }
+}
+
+/// The fingerprints that come with the crate, from the notes in `research/README.md`
+pub const BUILTIN: &[Fingerprint] = &[
+    Fingerprint::new(Model::Mouse, DeviceId::MOUSE),
+    Fingerprint {
+        escape: Some(false),
+        idle: [None, None, None, Some(0x00), Some(0x00), Some(0x00)],
+        ..Fingerprint::new(Model::NegCon, DeviceId::NEGCON)
+    },
+    Fingerprint {
+        escape: Some(false),
+        idle: [None, None, None, Some(0x80), Some(0x80), Some(0x80)],
+        ..Fingerprint::new(Model::Baton, DeviceId::NEGCON)
+    },
+    Fingerprint {
+        id_mask: 0xf0,
+        ..Fingerprint::new(Model::KonamiLightgun, DeviceId::new(0x30))
+    },
+    Fingerprint {
+        escape: Some(false),
+        ..Fingerprint::new(Model::Controller, DeviceId::CONTROLLER)
+    },
+    // Digital mode is shared by nearly everything
+    Fingerprint {
+        escape: Some(true),
+        generation: Some(0x01),
+        const1a: Some([0x00, 0x01, 0x02, 0x00, 0x0a]),
+        ..Fingerprint::new(Model::DualShock, DeviceId::DIGITAL)
+    },
+    Fingerprint {
+        escape: Some(true),
+        generation: Some(0x03),
+        ..Fingerprint::new(Model::DualShock2, DeviceId::DIGITAL)
+    },
+    Fingerprint {
+        escape: Some(true),
+        const1a: Some([0x00, 0x04, 0x03, 0x01, 0x1e]),
+        ..Fingerprint::new(Model::JogCon, DeviceId::DIGITAL)
+    },
+    Fingerprint {
+        escape: Some(false),
+        ..Fingerprint::new(Model::DvdRemote, DeviceId::DIGITAL)
+    },
+    Fingerprint {
+        escape: Some(false),
+        ..Fingerprint::new(Model::AnalogJoystick, DeviceId::ANALOG_GREEN)
+    },
+    Fingerprint {
+        escape: Some(false),
+        ..Fingerprint::new(Model::DualAnalog, DeviceId::ANALOG_GREEN)
+    },
+    Fingerprint::new(Model::GunCon, DeviceId::GUNCON),
+    Fingerprint {
+        escape: Some(true),
+        generation: Some(0x01),
+        const3b: Some([0x00, 0x00, 0x04, 0x00, 0x00]),
+        response_format: Some(false),
+        escape_poll: Some([0xff; 6]),
+        ..Fingerprint::new(Model::DualShock, DeviceId::DUALSHOCK)
+    },
+    Fingerprint {
+        escape: Some(true),
+        generation: Some(0x01),
+        const3b: Some([0x00, 0x00, 0x07, 0x00, 0x00]),
+        escape_poll: Some([0x7f, 0xff, 0x7f, 0x7f, 0x7f, 0x7f]),
+        ..Fingerprint::new(Model::GuitarHero, DeviceId::DUALSHOCK)
+    },
+    Fingerprint {
+        escape: Some(false),
+        ..Fingerprint::new(Model::DualAnalog, DeviceId::DUALSHOCK)
+    },
+    Fingerprint {
+        id_mask: 0xf0,
+        escape: Some(true),
+        generation: Some(0x03),
+        ..Fingerprint::new(Model::DualShock2, DeviceId::new(0x70))
+    },
+    Fingerprint {
+        id_mask: 0xf0,
+        ..Fingerprint::new(Model::Multitap, DeviceId::MULTITAP)
+    },
+    Fingerprint {
+        id_mask: 0xf0,
+        ..Fingerprint::new(Model::KeyboardAdapter, DeviceId::new(0x90))
+    },
+    Fingerprint::new(Model::JogCon, DeviceId::JOGCON),
+];
+
+/// A best guess at what a controller is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Identification {
+    /// What it most likely is
+    pub model: Model,
+    /// How much of its fingerprint matched, from 0 to 100
+    pub confidence: u8,
+    /// Another model that matched just as well, if there was one
+    pub runner_up: Option<Model>,
+}
+
+/// The fingerprints to identify controllers with. Fingerprints added with
+/// `Database::with` are checked before the built in ones and win any ties.
+#[derive(Clone, Copy)]
+pub struct Database<'a> {
+    custom: &'a [Fingerprint],
+}
+
+impl Default for Database<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Database<'a> {
+    /// Only the built in fingerprints
+    pub fn new() -> Self {
+        Self { custom: &[] }
+    }
+
+    /// The built in fingerprints, plus some of your own
+    pub fn with(custom: &'a [Fingerprint]) -> Self {
+        Self { custom }
+    }
+
+    /// Every fingerprint, yours first
+    pub fn fingerprints(&self) -> impl Iterator<Item = &Fingerprint> {
+        self.custom.iter().chain(BUILTIN.iter())
+    }
+
+    /// Find the model that best fits what was observed, going by how much of
+    /// each fingerprint matched. Ties go to whichever fingerprint came first.
+    pub fn identify(&self, observation: &Observation) -> Option<Identification> {
+        let mut best: Option<(Model, u8)> = None;
+        let mut runner_up = None;
+
+        for print in self.fingerprints() {
+            let (hits, total) = match print.score(observation) {
+                Some(x) => x,
+                None => continue,
+            };
+            let confidence = (u16::from(hits) * 100 / u16::from(total)) as u8;
+
+            match best {
+                Some((_, x)) if confidence < x => {}
+                Some((model, x)) if confidence == x => {
+                    if runner_up.is_none() && model != print.model {
+                        runner_up = Some(print.model);
+                    }
+                }
+                _ => {
+                    best = Some((print.model, confidence));
+                    runner_up = None;
+                }
+            }
+        }
+
+        best.map(|(model, confidence)| Identification {
+            model,
+            confidence,
+            runner_up,
+        })
+    }
+}
+
+impl<SPI, CS> PlayStationPort<SPI, CS>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+{
+    /// Poll the controller and, if it has an escape mode, ask it everything
+    /// that helps tell it apart from others. The response format is only tried
+    /// with the one the controller already says it's using, so nothing about
+    /// how it's set up changes.
+    pub fn observe(&mut self) -> Result<Observation, Error<SPI::Error>> {
+        let (id, data) = self.read_port(None)?;
+        let mut observation = Observation {
+            id,
+            data,
+            config: None,
+            response_format: false,
+            escape_poll: None,
+        };
+
+        if !id.present() {
+            return Ok(observation);
+        }
+
+        let mut session = match self.config_session() {
+            Ok(x) => x,
+            Err(Error::Unsupported) => return Ok(observation),
+            Err(x) => return Err(x),
+        };
+
+        observation.config = Some(session.read_config()?);
+
+        if let Response::Input(x) = session.command(Command::Poll { actuators: [0; 6] })? {
+            if let [a, b, c, d, e, f, ..] = *x.as_bytes() {
+                observation.escape_poll = Some([a, b, c, d, e, f]);
+            }
+        }
+
+        // Trying a response format on a controller that won't say which one
+        // it's using would mean changing it, so those don't get asked
+        match session.command(Command::QueryFormat) {
+            Ok(Response::Format(x)) if x != [0; 3] => {
+                observation.response_format = match session.command(Command::SetResponseFormat(x)) {
+                    Ok(_) => true,
+                    Err(Error::Unsupported) => false,
+                    Err(x) => return Err(x),
+                };
+            }
+            Ok(_) | Err(Error::Unsupported) => {}
+            Err(x) => return Err(x),
+        }
+
+        session.exit()?;
+
+        Ok(observation)
+    }
+
+    /// Work out what's plugged in. `None` if nothing in the database has the
+    /// controller's ID.
+    pub fn identify(
+        &mut self,
+        database: &Database,
+    ) -> Result<Option<Identification>, Error<SPI::Error>> {
+        let observation = self.observe()?;
+
+        Ok(database.identify(&observation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Database, Fingerprint, Model};
+    use crate::deviceid::DeviceId;
+    use crate::simulator::{Personality, Simulator};
+    use crate::{Device, PlayStationPort};
+
+    #[test]
+    fn identify_simulated() {
+        let sim = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+        let database = Database::new();
+
+        let expected = [
+            (Personality::Classic, Model::Controller),
+            (Personality::DualShock, Model::DualShock),
+            (Personality::DualShock2, Model::DualShock2),
+            (Personality::JogCon, Model::JogCon),
+            (Personality::GunCon, Model::GunCon),
+            (Personality::Mouse, Model::Mouse),
+        ];
+
+        for (personality, model) in expected.iter() {
+            sim.plug(*personality);
+            let guess = port.identify(&database).unwrap().unwrap();
+            assert_eq!(guess.model, *model);
+            assert_eq!(guess.runner_up, None);
+        }
+
+        // Shares 0x73 with the DualShock, but nothing else
+        sim.plug(Personality::GuitarHero);
+        let observation = port.observe().unwrap();
+        let guess = database.identify(&observation).unwrap();
+        assert_eq!(guess.model, Model::GuitarHero);
+        assert_eq!(guess.confidence, 100);
+        assert!(!sim.in_config_mode());
+        assert!(matches!(
+            guess.model.decode(observation.id, &observation.data),
+            Ok(Device::GuitarHero(_))
+        ));
+
+        // Same for the DualShock in analog mode
+        sim.plug(Personality::DualShock);
+        port.enable_pressure().unwrap();
+        let guess = port.identify(&database).unwrap().unwrap();
+        assert_eq!(guess.model, Model::DualShock);
+        assert_eq!(guess.confidence, 100);
+
+        sim.plug(Personality::Missing);
+        assert_eq!(port.identify(&database).unwrap(), None);
+    }
+
+    #[test]
+    fn identify_custom() {
+        let sim = Simulator::new(Personality::NegCon);
+        let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+
+        // A NeGcon and the baton look alike until someone touches them
+        sim.set_input(&[0xff, 0xff, 0x80, 0x00, 0x40, 0x00]);
+        let guess = port.identify(&Database::new()).unwrap().unwrap();
+        assert_eq!(guess.model, Model::NegCon);
+        assert_eq!(guess.runner_up, Some(Model::Baton));
+
+        sim.set_input(&[0xff, 0xff, 0x80, 0x00, 0x00, 0x00]);
+        let guess = port.identify(&Database::new()).unwrap().unwrap();
+        assert_eq!(guess.model, Model::NegCon);
+        assert_eq!(guess.runner_up, None);
+
+        // Our own wheel that's more particular about its twist
+        let wheel = [Fingerprint {
+            idle: [None, None, Some(0x80), Some(0x00), Some(0x00), Some(0x00)],
+            ..Fingerprint::new(Model::Custom(1), DeviceId::NEGCON)
+        }];
+        let guess = port.identify(&Database::with(&wheel)).unwrap().unwrap();
+        assert_eq!(guess.model, Model::Custom(1));
+        assert_eq!(guess.runner_up, Some(Model::NegCon));
+    }
+}
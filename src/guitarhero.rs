@@ -4,9 +4,14 @@
 //! to a DualShock1 controller. There's no way to tell it apart yet, so
 //! the design of this library doesn't make it easy to use yet
 
+use super::decoder::Decoder;
+use super::deviceid::DeviceId;
+use super::{check_length, ControllerData, DecodeError, Device};
+use byteorder::{ByteOrder, LittleEndian};
+use core::convert::TryFrom;
+
 /// Buttons on the Guitar Hero guitar. Reference material:
 /// https://strategywiki.org/wiki/Guitar_Hero_II/Controls
-#[repr(C)]
 #[derive(Copy, Clone)]
 pub struct GuitarButtons {
     data: u16,
@@ -27,6 +32,12 @@ impl GuitarButtons {
     const FRET_BLUE: u16 = 0x4000;
     const FRET_ORANGE: u16 = 0x8000;
 
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            data: LittleEndian::read_u16(bytes),
+        }
+    }
+
     /// A button on the controller
     pub fn select(&self) -> bool {
         self.data & Self::PS_SELECT == 0
@@ -78,16 +89,12 @@ impl GuitarButtons {
     }
 }
 
-#[repr(C)]
 #[derive(Copy, Clone)]
 /// Represents a Guitar Hero controller
 pub struct GuitarHero {
     /// The buttons
     pub buttons: GuitarButtons,
 
-    // Lazily pad bytes
-    padding: [u8; 3],
-
     /// The whammy bar's current position
     pub whammy: u8,
 }
@@ -98,3 +105,32 @@ impl GuitarHero {
         self.buttons
     }
 }
+
+impl TryFrom<&[u8]> for GuitarHero {
+    type Error = DecodeError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        check_length(data, 6)?;
+
+        // The whammy bar shows up as the last axis of the DualShock it pretends to be
+        Ok(Self {
+            buttons: GuitarButtons::from_bytes(data),
+            whammy: data[5],
+        })
+    }
+}
+
+/// Decodes the Guitar Hero controller. It answers with the DualShock's
+/// ID, so it isn't one of the built-in decoders. Add it to the port if you
+/// know that's what's plugged in.
+pub struct GuitarHeroDecoder;
+
+impl Decoder for GuitarHeroDecoder {
+    fn claims(&self, id: DeviceId, _: &ControllerData) -> bool {
+        id == DeviceId::DUALSHOCK
+    }
+
+    fn decode(&self, _: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        Ok(Device::GuitarHero(data.decode()?))
+    }
+}
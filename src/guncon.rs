@@ -2,11 +2,14 @@
 //! ============================
 //! A light gun for games like Time Crisis
 
+use super::decoder::Decoder;
+use super::deviceid::DeviceId;
+use super::{check_length, ControllerData, DecodeError, Device};
 use byteorder::{ByteOrder, LittleEndian};
+use core::convert::TryFrom;
 
 /// The buttons found on the generation 1 GunCon. Once I find a GC2
 /// I'll fill this out better
-#[repr(C)]
 #[derive(Copy, Clone)]
 pub struct GunconButtons {
     data: u16,
@@ -17,6 +20,12 @@ impl GunconButtons {
     const GC_B: u16 = 0x4000;
     const GC_TRIGGER: u16 = 0x2000;
 
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            data: LittleEndian::read_u16(bytes),
+        }
+    }
+
     /// A button on the controller
     pub fn a(&self) -> bool {
         self.data & Self::GC_A == 0
@@ -33,26 +42,52 @@ impl GunconButtons {
     }
 }
 
-#[repr(C)]
 #[derive(Copy, Clone)]
 /// Represents the classic Controller
 pub struct GunCon {
     /// Standard buttons (Cross, Circle, L3, Start)
     pub buttons: GunconButtons,
     /// Location on screen (left-right)
-    x: [u8; 2],
+    x: u16,
     /// Location on screen (up-down)
-    y: [u8; 2],
+    y: u16,
 }
 
 impl GunCon {
     /// Return the X position of the GunCon's aim on the screen
     pub fn x(&self) -> u16 {
-        LittleEndian::read_u16(&self.x)
+        self.x
     }
 
     /// Return the Y position of the GunCon's aim on the screen
     pub fn y(&self) -> u16 {
-        LittleEndian::read_u16(&self.y)
+        self.y
+    }
+}
+
+impl TryFrom<&[u8]> for GunCon {
+    type Error = DecodeError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        check_length(data, 6)?;
+
+        Ok(Self {
+            buttons: GunconButtons::from_bytes(data),
+            x: LittleEndian::read_u16(&data[2..4]),
+            y: LittleEndian::read_u16(&data[4..6]),
+        })
+    }
+}
+
+/// Decodes the GunCon
+pub struct GunConDecoder;
+
+impl Decoder for GunConDecoder {
+    fn claims(&self, id: DeviceId, _: &ControllerData) -> bool {
+        id == DeviceId::GUNCON
+    }
+
+    fn decode(&self, _: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        Ok(Device::GunCon(data.decode()?))
     }
 }
@@ -0,0 +1,219 @@
+//! Hot-plugging
+//! ============================
+//! Controllers come back from being unplugged (or a wireless receiver losing
+//! its pad) the way they were at power on: digital mode, no pressures, motors
+//! unmapped. A DualShock 2 that was set up with `enable_pressure` quietly turns
+//! into a `Device::Digital`.
+//!
+//! A `Connection` keeps an eye on the ID coming back from every poll. When a
+//! controller shows up, or the one that was there starts answering with a
+//! different ID, it runs the `Setup` again so it's back the way it was. The
+//! exception is a controller leaving analog mode, which follows the port's
+//! `AnalogPolicy` first: `Restore` puts it back without a fuss, `Report` gives
+//! an `Event::ModeChanged` and leaves it be.
+//!
+//! ```
+//! # #[cfg(feature = "simulator")] {
+//! use pscontroller_rs::deviceid::DeviceId;
+//! use pscontroller_rs::hotplug::{Connection, Event, Setup};
+//! use pscontroller_rs::simulator::{Personality, Simulator};
+//! use pscontroller_rs::{Device, PlayStationPort};
+//!
+//! let sim = Simulator::new(Personality::DualShock2);
+//! let port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+//! let mut connection = Connection::new(port, Setup::Pressure);
+//!
+//! let (device, event) = connection.poll(None).unwrap();
+//! assert_eq!(event, Some(Event::Connected(DeviceId::DUALSHOCK2)));
+//! assert!(matches!(device, Device::DualShock2(_)));
+//!
+//! // Unplug it...
+//! sim.plug(Personality::Missing);
+//! let (_, event) = connection.poll(None).unwrap();
+//! assert_eq!(event, Some(Event::Disconnected));
+//!
+//! // ...and plug it back in, fresh from power on
+//! sim.plug(Personality::DualShock2);
+//! let (device, event) = connection.poll(None).unwrap();
+//! assert_eq!(event, Some(Event::Connected(DeviceId::DUALSHOCK2)));
+//! assert!(matches!(device, Device::DualShock2(_)));
+//! # }
+//! ```
+
+use super::config::{ActuatorMap, AnalogPolicy};
+use super::deviceid::DeviceId;
+use super::dualshock::ResponseFormat;
+use super::{Device, Error, PlayStationPort, PollCommand};
+use hal::digital::OutputPin;
+use hal::spi::{ErrorType, SpiBus};
+
+/// A setup function of your own
+pub type SetupFn<SPI, CS> =
+    fn(&mut PlayStationPort<SPI, CS>) -> Result<(), Error<<SPI as ErrorType>::Error>>;
+
+/// Whatever needs to happen to a controller each time it's connected
+pub enum Setup<SPI, CS>
+where
+    SPI: SpiBus,
+{
+    /// Leave it the way it comes
+    Nothing,
+    /// `PlayStationPort::enable_pressure`
+    Pressure,
+    /// `PlayStationPort::enable_jogcon`
+    JogCon,
+    /// Something of your own
+    Custom(SetupFn<SPI, CS>),
+}
+
+/// Something that happened to the controller since the last poll
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// A controller showed up and was set up. Holds the ID it answers with
+    /// afterwards.
+    Connected(DeviceId),
+    /// The controller went away
+    Disconnected,
+    /// The controller left analog mode, and the port's `AnalogPolicy` is
+    /// `Report`. It's left in digital mode rather than being set up again.
+    ModeChanged,
+    /// The controller started answering with a different ID, so it was set
+    /// up again. This is what a DualShock 2 dropping back to digital mode
+    /// looks like, as well as one controller being swapped for another
+    /// between polls.
+    Changed {
+        /// The ID it had
+        from: DeviceId,
+        /// The ID it started answering with, before it was set up again.
+        /// `Connection::id` has the one it answers with now.
+        to: DeviceId,
+    },
+}
+
+/// A `PlayStationPort` that sets controllers up again whenever they're plugged
+/// back in
+pub struct Connection<SPI, CS>
+where
+    SPI: SpiBus,
+{
+    port: PlayStationPort<SPI, CS>,
+    setup: Setup<SPI, CS>,
+    /// The ID the controller answered with once it was set up, if there is one
+    id: Option<DeviceId>,
+}
+
+impl<SPI, CS> Connection<SPI, CS>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+{
+    /// Watch a port, running `setup` on every controller plugged into it.
+    /// Whatever's plugged in already gets set up on the first poll.
+    pub fn new(port: PlayStationPort<SPI, CS>, setup: Setup<SPI, CS>) -> Self {
+        Self {
+            port,
+            setup,
+            id: None,
+        }
+    }
+
+    /// Poll the controller just like `PlayStationPort::read_input`, setting it
+    /// up first if it's new or has changed
+    pub fn poll(
+        &mut self,
+        command: Option<&dyn PollCommand>,
+    ) -> Result<(Device, Option<Event>), Error<SPI::Error>> {
+        let (id, data, changed) = self.port.read_port_checked(command)?;
+
+        let event = match (self.id, id) {
+            (None, DeviceId::NOT_PRESENT) => None,
+            (Some(_), _) if changed && self.port.analog_policy == AnalogPolicy::Report => {
+                // Leave it in digital mode like the policy says
+                self.id = Some(id);
+                Some(Event::ModeChanged)
+            }
+            (Some(_), DeviceId::NOT_PRESENT) => {
+                self.forget();
+                Some(Event::Disconnected)
+            }
+            (Some(x), y) if x == y => None,
+            (previous, to) => {
+                // Whatever was set up before doesn't apply any more
+                self.forget();
+                self.run_setup()?;
+
+                // Poll again, so what comes back is from the controller as
+                // it is now
+                let (id, data) = self.port.read_port(command)?;
+                if id == DeviceId::NOT_PRESENT {
+                    return Ok((Device::None, previous.map(|_| Event::Disconnected)));
+                }
+
+                self.id = Some(id);
+                let device = self.port.decode_input(id, &data)?;
+
+                return Ok((
+                    device,
+                    Some(match previous {
+                        Some(from) => Event::Changed { from, to },
+                        None => Event::Connected(id),
+                    }),
+                ));
+            }
+        };
+
+        Ok((self.port.decode_input(id, &data)?, event))
+    }
+
+    /// Whether a controller was there at the last poll
+    pub fn connected(&self) -> bool {
+        self.id.is_some()
+    }
+
+    /// The ID the controller answered with once it was set up
+    pub fn id(&self) -> Option<DeviceId> {
+        self.id
+    }
+
+    /// Change what happens to newly connected controllers. The one that's
+    /// there already is set up again on the next poll.
+    pub fn set_setup(&mut self, setup: Setup<SPI, CS>) {
+        self.setup = setup;
+        self.id = None;
+    }
+
+    /// The port underneath, for anything else that needs doing
+    pub fn port(&mut self) -> &mut PlayStationPort<SPI, CS> {
+        &mut self.port
+    }
+
+    /// Stop watching and hand back the port
+    pub fn into_port(self) -> PlayStationPort<SPI, CS> {
+        self.port
+    }
+
+    /// Set up the controller. Controllers that don't support the setup, like
+    /// an original Controller asked for pressures, are used as they are.
+    fn run_setup(&mut self) -> Result<(), Error<SPI::Error>> {
+        let result = match self.setup {
+            Setup::Nothing => Ok(()),
+            Setup::Pressure => self.port.enable_pressure(),
+            Setup::JogCon => self.port.enable_jogcon(),
+            Setup::Custom(x) => x(&mut self.port),
+        };
+
+        match result {
+            Ok(()) | Err(Error::Unsupported) => Ok(()),
+            Err(x) => Err(x),
+        }
+    }
+
+    /// Drop what the port remembers about the controller, since a new one
+    /// starts out with everything at the defaults
+    fn forget(&mut self) {
+        self.id = None;
+        self.port.analog_lock = None;
+        self.port.format = ResponseFormat::ANALOG;
+        self.port.actuators = ActuatorMap::DUALSHOCK;
+    }
+}
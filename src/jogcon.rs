@@ -5,9 +5,13 @@
 //! but it is featureful enough that it can be treated as a servo motor with
 //! little effort.
 
-use super::{HasStandardButtons, PollCommand};
+use super::config::ActuatorMap;
+use super::decoder::Decoder;
+use super::deviceid::DeviceId;
+use super::{check_length, ControllerData, DecodeError, Device, HasStandardButtons, PollCommand};
 use crate::classic::GamepadButtons;
 use byteorder::{ByteOrder, LittleEndian};
+use core::convert::TryFrom;
 
 /// What we want the JogCon's wheel to do after we
 /// poll it
@@ -41,17 +45,15 @@ pub enum JogState {
     AtMaximum,
 }
 
-#[repr(C)]
 #[derive(Copy, Clone)]
 /// Represents the Namco JogCon controller
 pub struct JogCon {
-    // TODO: Implement an endian-safe accessor for jog_position
     // TODO: Implement an enum accessor for jog_state
     /// Standard buttons (Cross, Circle, L3, Start, etc)
     pub buttons: GamepadButtons,
 
     /// The absolute position of the jog wheel
-    jog_position: [u8; 2],
+    jog_position: i16,
 
     /// What state is the jog wheel in
     pub jog_state: u8,
@@ -60,7 +62,21 @@ pub struct JogCon {
 impl JogCon {
     /// The absolute position of the jog wheel
     pub fn jog_position(&self) -> i16 {
-        LittleEndian::read_i16(&self.jog_position)
+        self.jog_position
+    }
+}
+
+impl TryFrom<&[u8]> for JogCon {
+    type Error = DecodeError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        check_length(data, 5)?;
+
+        Ok(Self {
+            buttons: GamepadButtons::from_bytes(data),
+            jog_position: LittleEndian::read_i16(&data[2..4]),
+            jog_state: data[4],
+        })
     }
 }
 
@@ -89,7 +105,27 @@ impl ControlJC {
 impl PollCommand for ControlJC {
     /// Sets the command for the wheel on the JogCon
     fn set_command(&self, command: &mut [u8]) {
-        command[0] = self.mode.clone() as u8;
-        command[0] |= self.strength & 0x0f;
+        self.set_mapped_command(command, &ActuatorMap::JOGCON);
+    }
+
+    /// Sets the wheel in whichever slot its motor, actuator 0, was mapped to
+    fn set_mapped_command(&self, command: &mut [u8], actuators: &ActuatorMap) {
+        if let Some(x) = actuators.slot(0) {
+            command[x] = self.mode.clone() as u8;
+            command[x] |= self.strength & 0x0f;
+        }
+    }
+}
+
+/// Decodes the JogCon in its analog mode
+pub struct JogConDecoder;
+
+impl Decoder for JogConDecoder {
+    fn claims(&self, id: DeviceId, _: &ControllerData) -> bool {
+        id == DeviceId::JOGCON
+    }
+
+    fn decode(&self, _: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        Ok(Device::JogCon(data.decode()?))
     }
 }
@@ -8,8 +8,9 @@
 //!
 //! The current state of this library is such that it's pretty naïve to which controller
 //! is plugged in, and it will make a guess based on the response to a poll request. The
-//! response is broken into a header with an identification byte and an acknowledge byte and
-//! we use the whole identification byte and just assume the data its sending is correct.
+//! response is broken into a header with an identification byte and an acknowledge byte.
+//! We use the whole identification byte to pick the device, and the acknowledge byte to make
+//! sure whatever answered actually understood what we asked of it.
 //!
 //! If you own something particularly interesting for the PS1 or PS2 that plugs into the
 //! controller port and isn't supported here, feel free to reach out by creating an issue
@@ -36,6 +37,11 @@
 //! driven by the 7.5v cd-rom voltage. Testing using the 5v line on of the Raspberry Pi
 //! technically works, but the result is much weaker than the original console.
 //!
+//! Features
+//! -----------------------
+//! The crate is `no_std` by default. Turning on the `std` feature adds the things that need
+//! an allocator, like decoding memory card save titles into a `String`.
+//!
 //! Bibliography
 //! -----------------------
 //! Here is the list of the great bits of documentation that helped get this project started
@@ -47,17 +53,28 @@
 //! * [psxpblib](http://www.debaser.force9.co.uk/psxpblib/) - Interfacing PlayStation controllers via the parallel port
 //! * [Simulated PS2 Controller for Autonomously playing Guitar Hero](http://procrastineering.blogspot.ca/2010/12/simulated-ps2-controller-for.html) - SPI protocol captures
 
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(missing_docs)]
+#![forbid(unsafe_code)]
 
 pub mod baton;
 pub mod classic;
+pub mod command;
+pub mod config;
+pub mod decoder;
+pub mod deviceid;
 pub mod dualshock;
+pub mod fingerprint;
 pub mod guitarhero;
 pub mod guncon;
+pub mod hotplug;
 pub mod jogcon;
+pub mod memorycard;
 pub mod mouse;
 pub mod negcon;
+pub mod pocketstation;
+#[cfg(any(test, feature = "simulator"))]
+pub mod simulator;
 
 extern crate bit_reverse;
 extern crate bitflags;
@@ -65,13 +82,20 @@ extern crate byteorder;
 extern crate embedded_hal as hal;
 
 use bit_reverse::ParallelReverse;
+use command::{Command, COMMAND_LENGTH};
+use config::{ActuatorMap, AnalogMode, AnalogPolicy, ConfigSession};
+use core::convert::{Infallible, TryFrom};
 use core::fmt;
+use decoder::{CustomDevice, Decoder, MAX_DECODERS};
+use deviceid::{DeviceId, DeviceType};
 use hal::digital::OutputPin;
 use hal::spi::SpiBus;
+use memorycard::MemoryCard;
+use pocketstation::PocketStation;
 
 use baton::Baton;
 use classic::{Classic, GamepadButtons};
-use dualshock::{DualShock, DualShock2};
+use dualshock::{DualShock, DualShock2, PressureButton, ResponseFormat};
 use guitarhero::GuitarHero;
 use guncon::GunCon;
 use jogcon::JogCon;
@@ -80,82 +104,101 @@ use negcon::NegCon;
 
 /// The maximum length of a message from a controller
 const MESSAGE_MAX_LENGTH: usize = 32;
+/// The maximum length of a poll, header included
+const POLL_MAX_LENGTH: usize = HEADER_LEN + MESSAGE_MAX_LENGTH;
 /// Acknoweldgement byte for header commnad
-//const ACK_BYTE: u8 = 0x5a;
+const ACK_BYTE: u8 = 0x5a;
+/// What the bus reads as when nothing is driving it
+const BUS_IDLE: u8 = 0xff;
 /// Length of the command header
 const HEADER_LEN: usize = 3;
 
-/// Controller missing
-const CONTROLLER_NOT_PRESENT: u8 = 0xff;
-/// PlayStation mouse, SCPH-1030
-const CONTROLLER_MOUSE: u8 = 0x12;
-/// Original controller, SCPH-1080
-const CONTROLLER_CLASSIC: u8 = 0xc1;
-/// Analog Controller, SCPH-1110 (flightstick looking thing)
-const CONTROLLER_ANALOG_JOYSTICK: u8 = 0x53;
-/// DualShock in Digital mode
-const CONTROLLER_DUALSHOCK_DIGITAL: u8 = 0x41;
-/// DualShock
-const CONTROLLER_DUALSHOCK_ANALOG: u8 = 0x73;
-/// DuakShock 2
-const CONTROLLER_DUALSHOCK_PRESSURE: u8 = 0x79;
-/// JogCon
-const CONTROLLER_JOGCON: u8 = 0xe3;
-/// NegCon
-const CONTROLLER_NEGCON: u8 = 0x23;
-/// NegCon
-const CONTROLLER_GUNCON: u8 = 0x63;
-/// Configuration Mode
-const CONTROLLER_CONFIGURATION: u8 = 0xf3;
+/// Every controller we can decode. The low nybble of each is the number of
+/// 16bit words that device is expected to send back when polled.
+const KNOWN_CONTROLLERS: &[DeviceId] = &[
+    DeviceId::MOUSE,
+    DeviceId::CONTROLLER,
+    DeviceId::ANALOG_GREEN,
+    DeviceId::DIGITAL,
+    DeviceId::DUALSHOCK,
+    DeviceId::DUALSHOCK2,
+    DeviceId::JOGCON,
+    DeviceId::NEGCON,
+    DeviceId::GUNCON,
+    DeviceId::CONFIGURATION,
+];
 
 /// Command to poll buttons
 const CMD_POLL: &[u8] = &[0x00, 0x42, 0x00];
-/// Command to enter escape mode
-const CMD_ENTER_ESCAPE_MODE: &[u8] = &[0x00, 0x43, 0x00, 0x01, 0x00];
-/// Command to exit escape mode
-const CMD_EXIT_ESCAPE_MODE: &[u8] = &[0x00, 0x43, 0x00, 0x00, 0x00];
-/// Command to set response format. Right now asks for all data
-const CMD_RESPONSE_FORMAT: &[u8] = &[0x00, 0x4F, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x00, 0x00];
-/// Command to initialize / customize pressure
-const CMD_INIT_PRESSURE: &[u8] = &[0x00, 0x40, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00];
-/// Command to set major mode (DualShock = 1 / Digital = 0)
-const CMD_SET_MODE: &[u8] = &[0x00, 0x44, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00];
-/// Command to read extended status
-const CMD_READ_STATUS: &[u8] = &[0x00, 0x45, 0x00, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a];
-/// Command to read constant 1 at address 00
-const CMD_READ_CONST1A: &[u8] = &[0x00, 0x46, 0x00, 0x00, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a];
-/// Command to read constant 1 at address 01
-const CMD_READ_CONST1B: &[u8] = &[0x00, 0x46, 0x00, 0x01, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a];
-/// Command to read constant 2 at address 00
-const CMD_READ_CONST2: &[u8] = &[0x00, 0x47, 0x00, 0x00, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a];
-/// Command to read constant 3 at address 00
-const CMD_READ_CONST3A: &[u8] = &[0x00, 0x4C, 0x00, 0x00, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a];
-/// Command to read constant 3 at address 01
-const CMD_READ_CONST3B: &[u8] = &[0x00, 0x4C, 0x00, 0x01, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a];
-/// Command to enable DualShock motors
-const CMD_MOTOR_DUALSHOCK: &[u8] = &[0x00, 0x4D, 0x00, 0x00, 0x01, 0xff, 0xff, 0xff, 0xff];
-/// Command to enable JogCon motor
-const CMD_MOTOR_JOGCON: &[u8] = &[0x00, 0x4D, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff];
-
-#[repr(C)]
-#[derive(Copy, Clone)]
-/// The poll command returns a series of bytes. This union allows us to interact with
-/// those bytes with some functions. It's nice sugar that allows us to use the data
-/// in an obvious way without needing to copy the bytes around
-pub union ControllerData {
-    /// The raw data representing the buttons. Kind of unwieldy
+
+/// The poll command returns a series of bytes. This holds on to them with the
+/// header stripped off so they can be decoded into whichever device you know
+/// is plugged in, which is handy for the ones `read_input` can't tell apart.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ControllerData {
+    /// The raw data representing the buttons. Kind of unwieldy. Anything past
+    /// what the controller sent is zeroed
     pub data: [u8; MESSAGE_MAX_LENGTH],
-    /// Maps the underlying bytes to buttons and the whammy bar of the Guitar Hero controller
-    pub gh: GuitarHero,
-    /// Maps the bytes to the Mad Maestro baton
-    pub b: Baton,
-    pm: Mouse,
-    classic: Classic,
-    ds: DualShock,
-    ds2: DualShock2,
-    jc: JogCon,
-    nc: NegCon,
-    gc: GunCon,
+    length: usize,
+}
+
+impl ControllerData {
+    /// Wrap up some bytes from a controller. Anything past the maximum message
+    /// length is dropped.
+    pub fn new(bytes: &[u8]) -> Self {
+        let length = bytes.len().min(MESSAGE_MAX_LENGTH);
+        let mut data = [0u8; MESSAGE_MAX_LENGTH];
+
+        data[..length].copy_from_slice(&bytes[..length]);
+
+        Self { data, length }
+    }
+
+    /// Only the bytes the controller actually sent
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.length]
+    }
+
+    /// Decode the data as a particular device, whether or not that's what the
+    /// controller claimed to be. Fails if it didn't send enough data.
+    ///
+    /// ```
+    /// use pscontroller_rs::guitarhero::GuitarHero;
+    /// use pscontroller_rs::ControllerData;
+    ///
+    /// let data = ControllerData::new(&[0xff, 0xfd, 0x7f, 0x7f, 0x7f, 0x20]);
+    /// let guitar: GuitarHero = data.decode().unwrap();
+    ///
+    /// assert!(guitar.buttons.fret_green());
+    /// assert_eq!(guitar.whammy, 0x20);
+    /// ```
+    pub fn decode<'a, T>(&'a self) -> Result<T, DecodeError>
+    where
+        T: TryFrom<&'a [u8], Error = DecodeError>,
+    {
+        T::try_from(self.as_bytes())
+    }
+}
+
+/// Returned when there isn't enough data to decode a device
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodeError {
+    /// How many bytes the device needs
+    pub expected: usize,
+    /// How many bytes there were
+    pub actual: usize,
+}
+
+/// Make sure there's enough data to decode a device
+pub(crate) fn check_length(data: &[u8], expected: usize) -> Result<(), DecodeError> {
+    if data.len() < expected {
+        return Err(DecodeError {
+            expected,
+            actual: data.len(),
+        });
+    }
+
+    Ok(())
 }
 
 /// The active port to set on the Multitap
@@ -178,10 +221,43 @@ pub enum MultitapPort {
 
 /// Errors that can arrise from trying to communicate with the controller
 pub enum Error<E> {
-    /// Late collision
+    /// Late collision. Something drove the bus while we were still sending the
+    /// address, which nothing should ever do
     LateCollision,
-    /// Something responded badly
+    /// Something responded badly. The acknowledge byte wasn't 0x5A, which usually
+    /// means a loose connection or a controller that's still waking up
     BadResponse,
+    /// The controller announced a different amount of data than that type of
+    /// device is known to send
+    BadLength,
+    /// The decoder for the device couldn't make sense of what it sent
+    Decode(DecodeError),
+    /// Nothing answered. The controller is unplugged or not powered
+    NotPresent,
+    /// The controller didn't understand the command. Either it doesn't have an
+    /// escape (configuration) mode at all or it doesn't support that particular command
+    Unsupported,
+    /// The memory card's checksum didn't match the frame it was sent with
+    BadChecksum,
+    /// The memory card refused the frame number, either because it's past the
+    /// end of the card or because the sector is damaged
+    BadSector,
+    /// There's no room left, either for the blocks of a save on the card or for
+    /// the save in the buffer it's being read into
+    NoSpace,
+    /// No save starts at that block
+    NotFound,
+    /// The card's filesystem doesn't make sense. The header is missing or a save's
+    /// blocks don't link up
+    Corrupt,
+    /// A file isn't in any memory card format we know about
+    UnknownFormat,
+    /// A frame written to the memory card read back differently
+    VerifyFailed,
+    /// The controller left analog mode since the last poll, most likely
+    /// because someone pressed the Analog button. Only comes up with
+    /// `AnalogPolicy::Report`.
+    ModeChanged,
     /// SPI error
     Spi(E),
 }
@@ -194,7 +270,79 @@ impl<E> From<E> for Error<E> {
 
 impl<E> fmt::Debug for Error<E> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error")
+        match self {
+            Error::LateCollision => write!(f, "LateCollision"),
+            Error::BadResponse => write!(f, "BadResponse"),
+            Error::BadLength => write!(f, "BadLength"),
+            Error::Decode(x) => write!(f, "Decode({:?})", x),
+            Error::NotPresent => write!(f, "NotPresent"),
+            Error::Unsupported => write!(f, "Unsupported"),
+            Error::BadChecksum => write!(f, "BadChecksum"),
+            Error::BadSector => write!(f, "BadSector"),
+            Error::NoSpace => write!(f, "NoSpace"),
+            Error::NotFound => write!(f, "NotFound"),
+            Error::Corrupt => write!(f, "Corrupt"),
+            Error::UnknownFormat => write!(f, "UnknownFormat"),
+            Error::VerifyFailed => write!(f, "VerifyFailed"),
+            Error::ModeChanged => write!(f, "ModeChanged"),
+            Error::Spi(_) => write!(f, "Spi"),
+        }
+    }
+}
+
+impl Error<Infallible> {
+    /// Errors from things that can't fail at the bus level, like an image in
+    /// memory, fit in with any other kind of error
+    pub(crate) fn widen<E>(self) -> Error<E> {
+        match self {
+            Error::LateCollision => Error::LateCollision,
+            Error::BadResponse => Error::BadResponse,
+            Error::BadLength => Error::BadLength,
+            Error::Decode(x) => Error::Decode(x),
+            Error::NotPresent => Error::NotPresent,
+            Error::Unsupported => Error::Unsupported,
+            Error::BadChecksum => Error::BadChecksum,
+            Error::BadSector => Error::BadSector,
+            Error::NoSpace => Error::NoSpace,
+            Error::NotFound => Error::NotFound,
+            Error::Corrupt => Error::Corrupt,
+            Error::UnknownFormat => Error::UnknownFormat,
+            Error::VerifyFailed => Error::VerifyFailed,
+            Error::ModeChanged => Error::ModeChanged,
+            Error::Spi(x) => match x {},
+        }
+    }
+}
+
+impl<E> Error<E> {
+    /// Check the three byte header every response starts with
+    fn check_header(header: &[u8]) -> Result<(), Self> {
+        if header[0] != BUS_IDLE {
+            return Err(Error::LateCollision);
+        }
+
+        if header[1] == DeviceId::NOT_PRESENT.raw() {
+            return Err(Error::NotPresent);
+        }
+
+        if header[2] != ACK_BYTE {
+            return Err(Error::BadResponse);
+        }
+
+        Ok(())
+    }
+
+    /// Check the header of a response to a command sent in escape mode. The
+    /// controller should echo back that it's still in configuration mode. One
+    /// that answers properly with any other ID doesn't have an escape mode.
+    fn check_config_header(header: &[u8]) -> Result<(), Self> {
+        Self::check_header(header)?;
+
+        if header[1] != DeviceId::CONFIGURATION.raw() {
+            return Err(Error::Unsupported);
+        }
+
+        Ok(())
     }
 }
 
@@ -204,6 +352,13 @@ pub trait PollCommand {
     /// is called by read_input() which will provide a sub-slice of the
     /// controller's command bytes.
     fn set_command(&self, _: &mut [u8]);
+
+    /// Like `set_command`, but knowing which slots drive which actuator after
+    /// `PlayStationPort::set_actuators`. Commands that run motors should write
+    /// into the slots the map gives them. Falls back to `set_command`.
+    fn set_mapped_command(&self, command: &mut [u8], _actuators: &ActuatorMap) {
+        self.set_command(command);
+    }
 }
 
 /// Many controllers have the same set of buttons (Square, Circle, L3, R1, etc).
@@ -216,20 +371,22 @@ pub trait HasStandardButtons {
     fn buttons(&self) -> GamepadButtons;
 }
 
-/// Holds information about the controller's configuration and constants
-#[derive(Default)]
+/// Holds information about the controller's configuration and constants. The
+/// raw bytes are all here, and the parts that are understood can be read with
+/// methods like `generation` and `actuator`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct ControllerConfiguration {
-    /// The controller's current status and *perhaps* its generation
+    /// The controller's current status and its generation (0x45)
     pub status: [u8; 6],
-    /// Unknown constant
+    /// Details about the first actuator (0x46, address 0)
     pub const1a: [u8; 5],
-    /// Unknown constant
+    /// Details about the second actuator (0x46, address 1)
     pub const1b: [u8; 5],
-    /// Unknown constant
+    /// How many actuators there are, plus some unknown bytes (0x47)
     pub const2: [u8; 5],
-    /// Unknown constant
+    /// The device type of the first mode (0x4C, address 0)
     pub const3a: [u8; 5],
-    /// Unknown constant
+    /// The device type of the second mode (0x4C, address 1)
     pub const3b: [u8; 5],
 }
 
@@ -245,20 +402,32 @@ pub struct ControllerConfiguration {
 pub enum Device {
     /// If pulling the device type didn't work
     None,
-    /// A new controller type we haven't seen before
-    Unknown,
-    /// The controller is waiting for configuration data. Users of the library should
-    /// never need to see this state.
+    /// A new controller type we haven't seen before, or one we can't decode
+    /// yet like the Konami lightgun or the keyboard adapter
+    Unknown {
+        /// The ID it answered with
+        id: DeviceId,
+        /// Everything it sent after the header
+        data: ControllerData,
+    },
+    /// The controller is waiting for configuration data. `read_input` takes it
+    /// out of escape mode when it finds it there, so this only comes up when
+    /// that didn't work.
     ConfigurationMode,
     /// PlayStation mouse released at launch time with the original PlayStation
     Mouse(Mouse),
     /// Original controller that shipped with the PlayStation. Only contains regular
-    /// buttons. DualShock 1 and 2 can emulate this mode
+    /// buttons.
     Classic(Classic),
-    /// Sony's strange flight-stick looking thing. Maps to the same data as the
-    /// DualShock 1 but has a different identifier (fun fact: it predates the
-    /// DualShock)
-    AnalogJoystick(DualShock),
+    /// Anything sending only buttons under the digital ID, which is mostly
+    /// controllers with an Analog button like the DualShock 1 and 2 sitting in
+    /// digital mode. Sends the same buttons as the original controller.
+    Digital(Classic),
+    /// Sony's strange flight-stick looking thing, or the Analog Controller
+    /// (SCPH-1180) in its green mode. Maps to the same data as the DualShock 1
+    /// but has a different identifier, which the two of them share (fun fact:
+    /// they both predate the DualShock)
+    AnalogGreen(DualShock),
     /// Controller with two analog sticks. This was the final controller style shipped with
     /// the original PlayStation
     DualShock(DualShock),
@@ -275,6 +444,8 @@ pub enum Device {
     GunCon(GunCon),
     /// The Mad Maestro Baton
     Baton(Baton),
+    /// Something decoded by a decoder added with `PlayStationPort::add_decoder`
+    Custom(CustomDevice),
 }
 
 /// The main event! Create a port using an SPI bus and start commanding
@@ -283,6 +454,15 @@ pub struct PlayStationPort<SPI, CS> {
     dev: SPI,
     select: Option<CS>,
     multitap_port: MultitapPort,
+    format: ResponseFormat,
+    actuators: ActuatorMap,
+    /// Whether the Analog button was locked when we last put the controller
+    /// in analog mode, or `None` if we didn't
+    analog_lock: Option<bool>,
+    analog_policy: AnalogPolicy,
+    /// How many times a poll found the controller stuck in escape mode
+    escape_recoveries: u32,
+    decoders: [Option<&'static (dyn Decoder + Sync)>; MAX_DECODERS],
 }
 
 impl<SPI, CS> PlayStationPort<SPI, CS>
@@ -292,6 +472,10 @@ where
 {
     /// Create a new device to talk over the PlayStation's controller
     /// port
+    ///
+    /// With a `select` pin the port can keep the controller selected between
+    /// transfers, so polls only clock in as much data as the controller says it
+    /// has. Without one, every poll reads the largest possible response.
     pub fn new(spi: SPI, mut select: Option<CS>) -> Self {
         // If a select pin was provided, disable the controller for now
         if let Some(ref mut x) = select {
@@ -302,6 +486,12 @@ where
             dev: spi,
             select,
             multitap_port: MultitapPort::A,
+            format: ResponseFormat::ANALOG,
+            actuators: ActuatorMap::DUALSHOCK,
+            analog_lock: None,
+            analog_policy: AnalogPolicy::Ignore,
+            escape_recoveries: 0,
+            decoders: [None; MAX_DECODERS],
         }
     }
 
@@ -311,6 +501,66 @@ where
         }
     }
 
+    /// How many bytes follow the header, going by the low nybble of the ID byte.
+    /// Zero words means sixteen as the nybble can't count that high.
+    fn payload_length(header: &[u8]) -> usize {
+        if Error::<SPI::Error>::check_header(header).is_err() {
+            return 0;
+        }
+
+        DeviceId::new(header[1]).length()
+    }
+
+    /// Read the header, then only as much as the header says follows it
+    fn transfer_split(dev: &mut SPI, buffer: &mut [u8]) -> Result<usize, SPI::Error> {
+        dev.transfer_in_place(&mut buffer[..HEADER_LEN])?;
+
+        let mut header = [0u8; HEADER_LEN];
+        header.copy_from_slice(&buffer[..HEADER_LEN]);
+        Self::flip(&mut header);
+
+        let length = Self::payload_length(&header);
+        dev.transfer_in_place(&mut buffer[HEADER_LEN..HEADER_LEN + length])?;
+
+        Ok(length)
+    }
+
+    /// Clock a poll out to the controller. When we control the select line the
+    /// header is read on its own first and only the announced payload follows.
+    /// Returns how many bytes of payload were read.
+    fn transfer_poll(&mut self, buffer: &mut [u8; POLL_MAX_LENGTH]) -> Result<usize, SPI::Error> {
+        buffer[0] = self.multitap_port.clone() as u8;
+        Self::flip(buffer);
+
+        let length = match self.select {
+            Some(ref mut x) => {
+                let _ = x.set_low();
+
+                let result = Self::transfer_split(&mut self.dev, buffer);
+                let _ = x.set_high();
+                result?
+            }
+            None => {
+                self.dev.transfer_in_place(buffer)?;
+                Self::payload_length(&[
+                    buffer[0].swap_bits(),
+                    buffer[1].swap_bits(),
+                    buffer[2].swap_bits(),
+                ])
+            }
+        };
+
+        Self::flip(buffer);
+
+        // Anything past what the controller announced is either stale or never
+        // clocked in at all
+        for byte in buffer[HEADER_LEN + length..].iter_mut() {
+            *byte = 0;
+        }
+
+        Ok(length)
+    }
+
     /// Set the active port on the multi-tap. If no tap is being used, anything
     /// other than `A` will fail to return anything. Or so I assume! Setting this
     /// will mean any commands send will be directed towards that port indefinitely.
@@ -320,9 +570,21 @@ where
 
     /// Sends commands to the underlying hardware and provides responses
     pub fn send_command(&mut self, command: &[u8], result: &mut [u8]) -> Result<(), SPI::Error> {
+        let address = self.multitap_port.clone() as u8;
+
+        self.send_to(address, command, result)
+    }
+
+    /// Sends commands to whichever device on the bus answers to `address`
+    fn send_to(
+        &mut self,
+        address: u8,
+        command: &[u8],
+        result: &mut [u8],
+    ) -> Result<(), SPI::Error> {
         // Pack in bytes for the command we'll be sending
         result[..command.len()].copy_from_slice(command);
-        result[0] = self.multitap_port.clone() as u8;
+        result[0] = address;
 
         // Because not all hardware supports LSB mode for SPI, we flip
         // the bits ourselves
@@ -343,25 +605,68 @@ where
         Ok(())
     }
 
+    /// Talk to the memory card sharing the bus with the controller. The card
+    /// follows the multi-tap port selected with `set_multitap_port`.
+    pub fn memory_card(&mut self) -> MemoryCard<'_, SPI, CS> {
+        MemoryCard::new(self)
+    }
+
+    /// Talk to a PocketStation in the memory card slot. Fails with `Unsupported`
+    /// if it's an ordinary memory card.
+    pub fn pocketstation(&mut self) -> Result<PocketStation<'_, SPI, CS>, Error<SPI::Error>> {
+        PocketStation::detect(MemoryCard::new(self))
+    }
+
+    /// Send a command and make sure the controller acknowledged it
+    fn transact(&mut self, command: &[u8], result: &mut [u8]) -> Result<(), Error<SPI::Error>> {
+        self.send_command(command, result)?;
+
+        Error::check_header(result)
+    }
+
+    /// Send a command while in escape mode and make sure the controller is still there
+    fn configure(&mut self, command: &[u8], result: &mut [u8]) -> Result<(), Error<SPI::Error>> {
+        self.send_command(command, result)?;
+
+        match Error::check_config_header(result) {
+            // Still answering as a controller in escape mode, it just doesn't
+            // know the command so it never acknowledged it
+            Err(Error::BadResponse) if result[1] == DeviceId::CONFIGURATION.raw() => {
+                Err(Error::Unsupported)
+            }
+            x => x,
+        }
+    }
+
+    /// Put the controller into escape mode to configure it. It's taken back
+    /// out again when the session is dropped, even if something goes wrong.
+    pub fn config_session(&mut self) -> Result<ConfigSession<'_, SPI, CS>, Error<SPI::Error>> {
+        ConfigSession::new(self)
+    }
+
     /// Configure the controller to set it to DualShock2 mode. This will also
     /// enable analog mode on DualShock1 controllers.
-    pub fn enable_pressure(&mut self) -> Result<(), SPI::Error> {
+    pub fn enable_pressure(&mut self) -> Result<(), Error<SPI::Error>> {
         // TODO: Redefine this to allow input parameters. Right now they're are hard coded
-        // TODO: Detect and return actual protocol errors
 
-        let mut buffer = [0u8; MESSAGE_MAX_LENGTH];
+        let mut session = self.config_session()?;
 
-        // Wake up the controller if needed
-        self.send_command(CMD_POLL, &mut buffer)?;
+        session.set_mode(true, false)?;
+        session.map_actuators(ActuatorMap::DUALSHOCK)?;
 
-        self.send_command(CMD_ENTER_ESCAPE_MODE, &mut buffer)?;
-        self.send_command(CMD_SET_MODE, &mut buffer)?;
-        self.send_command(CMD_MOTOR_DUALSHOCK, &mut buffer)?;
-        self.send_command(CMD_INIT_PRESSURE, &mut buffer)?;
-        self.send_command(CMD_RESPONSE_FORMAT, &mut buffer)?;
-        self.send_command(CMD_EXIT_ESCAPE_MODE, &mut buffer)?;
+        // Only the DualShock 2 knows about pressure, so the others won't
+        // acknowledge these
+        let pressure = PressureButton::ALL
+            .iter()
+            .try_for_each(|x| session.set_pressure(*x, true))
+            .and_then(|()| session.set_response_format(ResponseFormat::ALL));
 
-        Ok(())
+        match pressure {
+            Ok(()) | Err(Error::Unsupported) => {}
+            Err(x) => return Err(x),
+        }
+
+        session.exit()
     }
 
     /// Configure the JogCon for wheel control.
@@ -370,47 +675,153 @@ where
     /// JogCon will go to sleep until buttons are pressed. If no polling is
     /// done for 10 seconds, it will drop out of this mode and revert to
     /// the standard Controller mode
-    pub fn enable_jogcon(&mut self) -> Result<(), SPI::Error> {
-        let mut buffer = [0u8; MESSAGE_MAX_LENGTH];
+    pub fn enable_jogcon(&mut self) -> Result<(), Error<SPI::Error>> {
+        let mut session = self.config_session()?;
 
-        // Wake up the controller if needed
-        self.send_command(CMD_POLL, &mut buffer)?;
+        session.set_mode(true, false)?;
+        session.map_actuators(ActuatorMap::JOGCON)?;
 
-        self.send_command(CMD_ENTER_ESCAPE_MODE, &mut buffer)?;
-        self.send_command(CMD_SET_MODE, &mut buffer)?;
-        self.send_command(CMD_MOTOR_JOGCON, &mut buffer)?;
-        self.send_command(CMD_EXIT_ESCAPE_MODE, &mut buffer)?;
+        session.exit()
+    }
 
-        Ok(())
+    /// Put the controller in digital or analog mode, and choose whether players
+    /// can change it with the Analog button. Works on the DualShock, DualShock 2
+    /// and Guitar Hero controller, though the Guitar Hero controller won't ever
+    /// leave analog mode. Controllers without an escape mode, like the original
+    /// Controller or the Dual Analog (SCPH-1180), fail with `Unsupported`.
+    pub fn set_analog_mode(
+        &mut self,
+        mode: AnalogMode,
+        locked: bool,
+    ) -> Result<(), Error<SPI::Error>> {
+        let mut session = self.config_session()?;
+
+        session.set_mode(mode == AnalogMode::Analog, locked)?;
+
+        session.exit()
     }
 
-    /// Read various parameters from the controller including its current
-    /// status.
-    pub fn read_config(&mut self) -> Result<ControllerConfiguration, SPI::Error> {
-        let mut config: ControllerConfiguration = Default::default();
-        let mut buffer = [0u8; MESSAGE_MAX_LENGTH];
+    /// Choose which bytes the DualShock 2 sends when polled. Leaving out the
+    /// sticks or pressures you don't need makes polls quicker. Only the
+    /// DualShock 2 and Guitar Hero controller support this, and changing the
+    /// analog mode puts it back to `ResponseFormat::ANALOG`.
+    pub fn set_response_format(&mut self, format: ResponseFormat) -> Result<(), Error<SPI::Error>> {
+        let mut session = self.config_session()?;
+
+        session.set_response_format(format)?;
 
-        self.send_command(CMD_ENTER_ESCAPE_MODE, &mut buffer)?;
+        session.exit()
+    }
+
+    /// Only measure and send the pressures of some of the DualShock 2's buttons,
+    /// along with the buttons and sticks. Puts the controller in analog mode
+    /// if it isn't already. Returns the format the controller says it's using
+    /// afterwards.
+    pub fn set_pressures(
+        &mut self,
+        buttons: &[PressureButton],
+    ) -> Result<ResponseFormat, Error<SPI::Error>> {
+        let mut session = self.config_session()?;
+
+        if session.analog_mode()? == AnalogMode::Digital {
+            session.set_mode(true, false)?;
+        }
 
-        self.send_command(CMD_READ_STATUS, &mut buffer)?;
-        config.status.copy_from_slice(&buffer[HEADER_LEN..9]);
+        for button in PressureButton::ALL.iter() {
+            session.set_pressure(*button, buttons.contains(button))?;
+        }
 
-        self.send_command(CMD_READ_CONST1A, &mut buffer)?;
-        config.const1a.copy_from_slice(&buffer[4..9]);
+        session.set_response_format(ResponseFormat::ANALOG.with_pressures(buttons))?;
+        let format = session.response_format()?;
 
-        self.send_command(CMD_READ_CONST1B, &mut buffer)?;
-        config.const1b.copy_from_slice(&buffer[4..9]);
+        session.exit()?;
 
-        self.send_command(CMD_READ_CONST2, &mut buffer)?;
-        config.const2.copy_from_slice(&buffer[4..9]);
+        Ok(format)
+    }
 
-        self.send_command(CMD_READ_CONST3A, &mut buffer)?;
-        config.const3a.copy_from_slice(&buffer[4..9]);
+    /// Ask the DualShock 2 which bytes it's sending when polled
+    pub fn query_response_format(&mut self) -> Result<ResponseFormat, Error<SPI::Error>> {
+        let mut session = self.config_session()?;
+        let format = session.response_format()?;
 
-        self.send_command(CMD_READ_CONST3B, &mut buffer)?;
-        config.const3b.copy_from_slice(&buffer[4..9]);
+        session.exit()?;
 
-        self.send_command(CMD_EXIT_ESCAPE_MODE, &mut buffer)?;
+        Ok(format)
+    }
+
+    /// The response format `read_input` expects the DualShock 2 to use
+    pub fn response_format(&self) -> ResponseFormat {
+        self.format
+    }
+
+    /// Choose which bytes of the poll command drive which motor. `PollCommand`s
+    /// like `ControlDS` follow the map, so it works with controllers that lay
+    /// their motors out differently. `ActuatorMap::NONE` turns off rumble
+    /// altogether.
+    pub fn set_actuators(&mut self, map: ActuatorMap) -> Result<(), Error<SPI::Error>> {
+        let mut session = self.config_session()?;
+
+        session.map_actuators(map)?;
+
+        session.exit()
+    }
+
+    /// Which slots `PollCommand`s write their actuators into
+    pub fn actuators(&self) -> ActuatorMap {
+        self.actuators
+    }
+
+    /// Choose what happens when a controller we put in analog mode drops back
+    /// to digital mode, such as when someone presses the Analog button.
+    /// Locking the mode with `set_analog_mode` stops that from happening in
+    /// the first place, but not every controller can be locked.
+    pub fn set_analog_policy(&mut self, policy: AnalogPolicy) {
+        self.analog_policy = policy;
+    }
+
+    /// What happens when the controller leaves analog mode
+    pub fn analog_policy(&self) -> AnalogPolicy {
+        self.analog_policy
+    }
+
+    /// Put the controller back in analog mode after it left, with the same
+    /// actuators, pressures and response format it had
+    fn restore_analog(&mut self, locked: bool) -> Result<(), Error<SPI::Error>> {
+        let format = self.format;
+        let actuators = self.actuators;
+        let mut session = self.config_session()?;
+
+        session.set_mode(true, locked)?;
+        session.map_actuators(actuators)?;
+
+        if format != ResponseFormat::ANALOG {
+            for button in PressureButton::ALL.iter() {
+                session.set_pressure(*button, format.has_pressure(*button))?;
+            }
+            session.set_response_format(format)?;
+        }
+
+        session.exit()
+    }
+
+    /// Ask the controller which mode it's in. Fails with `Unsupported` on
+    /// controllers that can't tell us, just like `set_analog_mode`.
+    pub fn analog_mode(&mut self) -> Result<AnalogMode, Error<SPI::Error>> {
+        let mut session = self.config_session()?;
+        let mode = session.analog_mode()?;
+
+        session.exit()?;
+
+        Ok(mode)
+    }
+
+    /// Read various parameters from the controller including its current
+    /// status.
+    pub fn read_config(&mut self) -> Result<ControllerConfiguration, Error<SPI::Error>> {
+        let mut session = self.config_session()?;
+        let config = session.read_config()?;
+
+        session.exit()?;
 
         Ok(config)
     }
@@ -418,33 +829,116 @@ where
     fn read_port(
         &mut self,
         command: Option<&dyn PollCommand>,
-    ) -> Result<[u8; MESSAGE_MAX_LENGTH], Error<SPI::Error>> {
-        let mut buffer = [0u8; MESSAGE_MAX_LENGTH];
-        let mut data = [0u8; MESSAGE_MAX_LENGTH];
+    ) -> Result<(DeviceId, ControllerData), Error<SPI::Error>> {
+        let mut buffer = [0u8; POLL_MAX_LENGTH];
 
-        data[..CMD_POLL.len()].copy_from_slice(CMD_POLL);
+        buffer[..CMD_POLL.len()].copy_from_slice(CMD_POLL);
 
         // Overlay the command to send with the poll...
         if let Some(x) = command {
-            x.set_command(&mut data[HEADER_LEN..]);
+            x.set_mapped_command(&mut buffer[HEADER_LEN..], &self.actuators);
         }
 
-        self.send_command(&data, &mut buffer)?;
+        let length = self.transfer_poll(&mut buffer)?;
 
         // Device polling will return `ACK_BYTE` in the third byte if the command
-        // was properly understood
-        /*
-        match device {
-            Device::None => {},
-            _ => {
-                if buffer[2] != ACK_BYTE {
-                    return Err(Error::BadResponse);
-                }
+        // was properly understood. A missing controller isn't an error here though,
+        // it's reported as `Device::None`
+        match Error::check_header(&buffer) {
+            Ok(()) | Err(Error::NotPresent) => {}
+            Err(x) => return Err(x),
+        }
+
+        // We don't need the header anymore
+        let data = ControllerData::new(&buffer[HEADER_LEN..HEADER_LEN + length]);
+
+        Ok((DeviceId::new(buffer[1]), data))
+    }
+
+    /// Get a controller out of escape mode when something left it there, like
+    /// a configuration session cut short by a bad transfer. If we'd put it in
+    /// analog mode it's put back the way it was meant to be.
+    fn recover_escape(&mut self) -> Result<(), Error<SPI::Error>> {
+        let mut command = [0u8; COMMAND_LENGTH];
+        let mut result = [0u8; COMMAND_LENGTH];
+
+        Command::Escape(false).write(&mut command);
+        self.configure(&command, &mut result)?;
+
+        self.escape_recoveries = self.escape_recoveries.wrapping_add(1);
+
+        match self.analog_lock {
+            Some(locked) => self.restore_analog(locked),
+            None => Ok(()),
+        }
+    }
+
+    /// How many times a poll found the controller stuck in escape mode and
+    /// had to take it out again. Happening a lot usually means the wiring
+    /// isn't great.
+    pub fn escape_recoveries(&self) -> u32 {
+        self.escape_recoveries
+    }
+
+    /// Poll, then deal with the controller being stuck in escape mode or
+    /// having left analog mode according to the analog policy. Also says
+    /// whether it had left analog mode.
+    fn read_port_checked(
+        &mut self,
+        command: Option<&dyn PollCommand>,
+    ) -> Result<(DeviceId, ControllerData, bool), Error<SPI::Error>> {
+        let (mut id, mut data) = self.read_port(command)?;
+
+        // Try the poll again once it's out. If it's still stuck it gets
+        // reported as `Device::ConfigurationMode`.
+        if id == DeviceId::CONFIGURATION {
+            self.recover_escape()?;
+            (id, data) = self.read_port(command)?;
+        }
+
+        let locked = match self.analog_lock {
+            Some(x) if id == DeviceId::DIGITAL => x,
+            _ => return Ok((id, data, false)),
+        };
+
+        match self.analog_policy {
+            AnalogPolicy::Ignore | AnalogPolicy::Report => {
+                // It's back the way it started, so stop expecting otherwise
+                self.analog_lock = None;
+                self.format = ResponseFormat::ANALOG;
+
+                Ok((id, data, true))
+            }
+            AnalogPolicy::Restore => {
+                self.restore_analog(locked)?;
+                let (id, data) = self.read_port(command)?;
+
+                Ok((id, data, true))
             }
         }
-        */
+    }
+
+    /// Have `read_input` decode devices with a decoder of your own. Decoders
+    /// are tried in the order they were added, before any of the built-in
+    /// ones. Fails with `NoSpace` once there are `MAX_DECODERS` of them.
+    pub fn add_decoder(
+        &mut self,
+        decoder: &'static (dyn Decoder + Sync),
+    ) -> Result<(), Error<SPI::Error>> {
+        let slot = self
+            .decoders
+            .iter_mut()
+            .find(|x| x.is_none())
+            .ok_or(Error::NoSpace)?;
+
+        *slot = Some(decoder);
 
-        Ok(buffer)
+        Ok(())
+    }
+
+    /// Go back to only the built-in decoders
+    pub fn clear_decoders(&mut self) {
+        self.decoders = [None; MAX_DECODERS];
     }
 
     /// Get the raw data from polling for a controller. You can use this to cooerce the data into
@@ -454,16 +948,9 @@ where
         &mut self,
         command: Option<&dyn PollCommand>,
     ) -> Result<ControllerData, Error<SPI::Error>> {
-        let mut buffer = [0u8; MESSAGE_MAX_LENGTH];
-        let data = self.read_port(command)?;
-
-        // Shift the controller data over because we don't need the header anymore
-        buffer[0..MESSAGE_MAX_LENGTH - 3].copy_from_slice(&data[HEADER_LEN..]);
-
-        // FIXME: This doesn't zero out the end so we could theoretically have
-        //        repeated data
+        let (_, data) = self.read_port(command)?;
 
-        Ok(ControllerData { data: buffer })
+        Ok(data)
     }
 
     /// Ask the controller for input states. Different contoller types will be returned automatically
@@ -472,43 +959,89 @@ where
         &mut self,
         command: Option<&dyn PollCommand>,
     ) -> Result<Device, Error<SPI::Error>> {
-        let mut buffer = [0u8; MESSAGE_MAX_LENGTH];
-        let data = self.read_port(command)?;
-
-        // Shift the controller data over because we don't need the header anymore
-        buffer[0..MESSAGE_MAX_LENGTH - 3].copy_from_slice(&data[HEADER_LEN..]);
-
-        let controller = ControllerData { data: buffer };
-        let device;
-
-        unsafe {
-            device = match data[1] {
-                CONTROLLER_NOT_PRESENT => Device::None,
-                CONTROLLER_CONFIGURATION => Device::ConfigurationMode,
-                CONTROLLER_MOUSE => Device::Mouse(controller.pm),
-                CONTROLLER_CLASSIC => Device::Classic(controller.classic),
-                CONTROLLER_ANALOG_JOYSTICK => Device::AnalogJoystick(controller.ds),
-                CONTROLLER_DUALSHOCK_DIGITAL => Device::Classic(controller.classic),
-                CONTROLLER_DUALSHOCK_ANALOG => Device::DualShock(controller.ds),
-                CONTROLLER_DUALSHOCK_PRESSURE => Device::DualShock2(controller.ds2),
-                CONTROLLER_JOGCON => Device::JogCon(controller.jc),
-                CONTROLLER_NEGCON => Device::NegCon(controller.nc),
-                CONTROLLER_GUNCON => Device::GunCon(controller.gc),
-                _ => Device::Unknown,
-            }
+        let (id, controller, changed) = self.read_port_checked(command)?;
+
+        if changed && self.analog_policy == AnalogPolicy::Report {
+            return Err(Error::ModeChanged);
+        }
+
+        self.decode_input(id, &controller)
+    }
+
+    /// Turn the ID and data from a poll into whichever device sent them
+    fn decode_input(
+        &self,
+        id: DeviceId,
+        controller: &ControllerData,
+    ) -> Result<Device, Error<SPI::Error>> {
+        match id {
+            DeviceId::NOT_PRESENT => return Ok(Device::None),
+            DeviceId::CONFIGURATION => return Ok(Device::ConfigurationMode),
+            _ => {}
+        }
+
+        // Your own decoders come first, so they can take over any ID
+        if let Some(x) = self
+            .decoders
+            .iter()
+            .flatten()
+            .find(|x| x.claims(id, controller))
+        {
+            return x.decode(id, controller).map_err(Error::Decode);
         }
 
-        Ok(device)
+        // Whatever format we asked for, as long as it isn't the same as the
+        // DualShock's
+        if id.kind() == DeviceType::Analog
+            && id.words() == self.format.words()
+            && self.format != ResponseFormat::ANALOG
+        {
+            return DualShock2::decode(controller.as_bytes(), self.format)
+                .map(Device::DualShock2)
+                .map_err(Error::Decode);
+        }
+
+        if let Some(x) = decoder::BUILTIN.iter().find(|x| x.claims(id, controller)) {
+            return x.decode(id, controller).map_err(Error::Decode);
+        }
+
+        // We know this type of device, but not at this length
+        if KNOWN_CONTROLLERS.iter().any(|x| x.kind() == id.kind()) {
+            return Err(Error::BadLength);
+        }
+
+        Ok(Device::Unknown {
+            id,
+            data: *controller,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ControllerData;
+    use super::classic::Classic;
+    use super::command::{Command, Response};
+    use super::config::{
+        Actuator, ActuatorKind, ActuatorMap, AnalogMode, AnalogPolicy, Generation,
+    };
+    use super::decoder::{Custom, Decoder, MAX_DECODERS};
+    use super::deviceid::{DeviceId, DeviceType};
+    use super::dualshock::{ControlDS, DualShock, PressureButton, ResponseFormat};
+    use super::fingerprint::Model;
+    use super::guitarhero::GuitarHeroDecoder;
+    use super::hotplug::{Connection, Event, Setup};
+    use super::jogcon::JogCon;
+    use super::memorycard::{Flags, FRAME_SIZE};
+    use super::mouse::Mouse;
+    use super::pocketstation::{Date, Time};
+    use super::simulator::{Fault, Personality, SimulatedSelect, Simulator};
     use super::MESSAGE_MAX_LENGTH;
+    use super::{ControllerData, DecodeError, Error};
+    use super::{Device, MultitapPort, PlayStationPort};
+    use core::convert::TryFrom;
 
     #[test]
-    fn union_test() {
+    fn decode_test() {
         // Again, buttons are active low, hence 'fe' and '7f'
         let mut data = [0u8; MESSAGE_MAX_LENGTH];
         data[0] = 0xfe;
@@ -517,13 +1050,871 @@ mod tests {
         data[3] = 0x00;
         data[4] = 0x00;
         data[5] = 0xff;
-        let controller = ControllerData { data };
+        let controller = ControllerData::new(&data[..6]);
+        let ds: DualShock = controller.decode().unwrap();
+
+        assert!(ds.buttons.select());
+        assert!(ds.buttons.square());
+        assert!(ds.lx == 0);
+        assert!(ds.ly == 255);
+    }
 
-        unsafe {
-            assert!(controller.ds.buttons.select() == true);
-            assert!(controller.ds.buttons.square() == true);
-            assert!(controller.ds.lx == 0);
-            assert!(controller.ds.ly == 255);
+    #[test]
+    fn decode_too_short() {
+        let controller = ControllerData::new(&[0xff, 0xff, 0x80, 0x80]);
+
+        assert!(controller.decode::<Classic>().is_ok());
+        assert!(controller.decode::<Mouse>().is_ok());
+        assert_eq!(
+            controller.decode::<DualShock>().err(),
+            Some(DecodeError {
+                expected: 6,
+                actual: 4
+            })
+        );
+        assert!(JogCon::try_from(&controller.data[..4]).is_err());
+        assert!(JogCon::try_from(&controller.data[..5]).is_ok());
+    }
+
+    #[test]
+    fn simulated_poll() {
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        // Square and Select held down
+        pad.set_input(&[0xfe, 0x7f]);
+
+        match port.read_input(None).unwrap() {
+            Device::Digital(x) => {
+                assert!(x.buttons.select());
+                assert!(x.buttons.square());
+                assert!(!x.buttons.cross());
+            }
+            _ => panic!("DualShock should start in digital mode"),
+        }
+
+        let pad = Simulator::new(Personality::Missing);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+        assert!(matches!(port.read_input(None).unwrap(), Device::None));
+    }
+
+    #[test]
+    fn simulated_devices() {
+        let pad = Simulator::new(Personality::Mouse);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+        pad.set_input(&[0xff, 0xf7, 0x05, 0xfe]);
+        match port.read_input(None).unwrap() {
+            Device::Mouse(x) => {
+                assert!(x.buttons.left());
+                assert!(!x.buttons.right());
+                assert_eq!(x.y, 5);
+                assert_eq!(x.x, -2);
+            }
+            _ => panic!("expected a mouse"),
         }
+
+        pad.plug(Personality::GunCon);
+        match port.read_input(None).unwrap() {
+            Device::GunCon(x) => {
+                assert_eq!(x.x(), 10);
+                assert_eq!(x.y(), 1);
+            }
+            _ => panic!("expected a GunCon"),
+        }
+
+        pad.plug(Personality::NegCon);
+        assert!(matches!(port.read_input(None).unwrap(), Device::NegCon(_)));
+
+        pad.plug(Personality::Classic);
+        assert!(matches!(port.read_input(None).unwrap(), Device::Classic(_)));
+
+        pad.plug(Personality::GuitarHero);
+        assert!(matches!(
+            port.read_input(None).unwrap(),
+            Device::DualShock(_)
+        ));
+    }
+
+    #[test]
+    fn simulated_enable_pressure() {
+        let pad = Simulator::new(Personality::DualShock2);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        port.enable_pressure().unwrap();
+        assert!(pad.is_analog());
+        assert!(!pad.in_config_mode());
+        assert_eq!(pad.actuators(), [0x00, 0x01, 0xff, 0xff, 0xff, 0xff]);
+
+        let mut input = [0u8; 18];
+        input[..6].copy_from_slice(&[0xff, 0xbf, 0x10, 0x20, 0x30, 0x40]);
+        input[12] = 0xaa;
+        pad.set_input(&input);
+
+        match port.read_input(None).unwrap() {
+            Device::DualShock2(x) => {
+                assert!(x.buttons.cross());
+                assert_eq!((x.rx, x.ry, x.lx, x.ly), (0x10, 0x20, 0x30, 0x40));
+                assert_eq!(x.pressures[6], 0xaa);
+            }
+            _ => panic!("expected a DualShock 2"),
+        }
+
+        // A DualShock 1 ignores the pressure commands but still goes analog
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        port.enable_pressure().unwrap();
+        assert!(matches!(
+            port.read_input(None).unwrap(),
+            Device::DualShock(_)
+        ));
+    }
+
+    #[test]
+    fn simulated_enable_jogcon() {
+        let pad = Simulator::new(Personality::JogCon);
+        let mut port = PlayStationPort::<_, SimulatedSelect>::new(pad.bus(), None);
+
+        port.enable_jogcon().unwrap();
+        assert_eq!(pad.actuators(), [0x00, 0xff, 0xff, 0xff, 0xff, 0xff]);
+
+        pad.set_input(&[0xff, 0xff, 0xfe, 0xff, 0x02, 0x00]);
+        match port.read_input(None).unwrap() {
+            Device::JogCon(x) => {
+                assert_eq!(x.jog_position(), -2);
+                assert_eq!(x.jog_state, 0x02);
+            }
+            _ => panic!("expected a JogCon"),
+        }
+    }
+
+    #[test]
+    fn config_session_always_exits() {
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::<_, SimulatedSelect>::new(pad.bus(), None);
+
+        // The DualShock doesn't know about response formats, so bailing out
+        // with `?` drops the session part way through
+        let mut configure = || -> Result<(), Error<_>> {
+            let mut session = port.config_session()?;
+            session.set_mode(true, true)?;
+            session.set_response_format(ResponseFormat::ANALOG)?;
+            session.exit()
+        };
+
+        assert!(matches!(configure(), Err(Error::Unsupported)));
+        assert!(!pad.in_config_mode());
+        assert!(pad.is_analog() && pad.is_locked());
+
+        let mut session = port.config_session().unwrap();
+        assert_eq!(session.send(&[0x00, 0x45, 0x00]).unwrap(), &[]);
+        assert!(matches!(session.send(&[0x01, 0x45]), Err(Error::BadLength)));
+        assert_eq!(session.read_status().unwrap()[..3], [0x01, 0x02, 0x01]);
+        assert!(pad.in_config_mode());
+        drop(session);
+        assert!(!pad.in_config_mode());
+
+        // Going into escape mode with the reply mangled on the way back still
+        // gets it taken back out
+        pad.set_fault(Some(Fault::Garbled));
+        assert!(matches!(port.config_session(), Err(Error::BadResponse)));
+        pad.set_fault(None);
+        assert!(!pad.in_config_mode());
+
+        // The original controller has no escape mode to go into, or to be
+        // taken back out of
+        pad.plug(Personality::Classic);
+        let before = pad.escapes();
+        assert!(matches!(port.config_session(), Err(Error::Unsupported)));
+        assert_eq!(pad.escapes() - before, 1);
+    }
+
+    #[test]
+    fn config_commands() {
+        let pad = Simulator::new(Personality::DualShock2);
+        let mut port = PlayStationPort::<_, SimulatedSelect>::new(pad.bus(), None);
+
+        port.enable_pressure().unwrap();
+        pad.set_input(&[0xfe, 0xff, 0x01, 0x02, 0x03, 0x04]);
+
+        // Going into escape mode polls with whatever format was set up
+        let mut session = port.config_session().unwrap();
+        assert_eq!(session.input().as_bytes().len(), 18);
+        assert_eq!(
+            session.input().as_bytes()[..6],
+            [0xfe, 0xff, 0x01, 0x02, 0x03, 0x04]
+        );
+
+        assert_eq!(
+            session.command(Command::QueryFormat).unwrap(),
+            Response::Format([0xff, 0xff, 0x03])
+        );
+        assert_eq!(
+            session.command(Command::ReadExtra).unwrap(),
+            Response::Raw([0x05, 0x01, 0x02, 0x00, 0x00, 0x00])
+        );
+        assert_eq!(
+            session
+                .command(Command::ReadConstant3 { address: 1 })
+                .unwrap(),
+            Response::Constant([0x00, 0x00, 0x07, 0x00, 0x00])
+        );
+        assert_eq!(
+            session
+                .command(Command::MapActuators([0x01, 0x00, 0xff, 0xff, 0xff, 0xff]))
+                .unwrap(),
+            Response::Actuators([0x00, 0x01, 0xff, 0xff, 0xff, 0xff])
+        );
+        match session
+            .command(Command::Poll { actuators: [0; 6] })
+            .unwrap()
+        {
+            Response::Input(x) => assert_eq!(x.as_bytes(), &[0xfe, 0xff, 0x01, 0x02, 0x03, 0x04]),
+            _ => panic!("expected input"),
+        }
+
+        // Leaving with the command itself means there's no exit left to send
+        assert!(matches!(
+            session.command(Command::Escape(false)),
+            Ok(Response::Input(_))
+        ));
+        assert!(!pad.in_config_mode());
+        let before = pad.clocked();
+        drop(session);
+        assert_eq!(pad.clocked(), before);
+
+        let command = Command::SetMode {
+            analog: true,
+            locked: true,
+        };
+        assert_eq!(Command::from_bytes(0x44, command.arguments()), command);
+        assert!(matches!(
+            Command::from_bytes(0x48, [0; 6]),
+            Command::Other { command: 0x48, .. }
+        ));
+    }
+
+    #[test]
+    fn analog_mode() {
+        let pad = Simulator::new(Personality::DualShock2);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        assert_eq!(port.analog_mode().unwrap(), AnalogMode::Digital);
+        port.set_analog_mode(AnalogMode::Analog, true).unwrap();
+        assert_eq!(port.analog_mode().unwrap(), AnalogMode::Analog);
+
+        // Locked, so the player can't drop out of analog mode
+        pad.press_analog_button();
+        assert!(matches!(port.read_input(None), Ok(Device::DualShock(_))));
+
+        port.set_analog_mode(AnalogMode::Analog, false).unwrap();
+        pad.press_analog_button();
+        assert_eq!(port.analog_mode().unwrap(), AnalogMode::Digital);
+        assert!(matches!(port.read_input(None), Ok(Device::Digital(_))));
+
+        // Always in analog mode, but never says so in its status
+        pad.plug(Personality::GuitarHero);
+        port.set_analog_mode(AnalogMode::Digital, false).unwrap();
+        assert_eq!(port.analog_mode().unwrap(), AnalogMode::Analog);
+
+        pad.plug(Personality::Classic);
+        assert!(matches!(port.analog_mode(), Err(Error::Unsupported)));
+        assert!(matches!(
+            port.set_analog_mode(AnalogMode::Analog, false),
+            Err(Error::Unsupported)
+        ));
+    }
+
+    #[test]
+    fn response_format() {
+        let pad = Simulator::new(Personality::DualShock2);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+        let mut input = [0u8; 18];
+
+        for (i, x) in input.iter_mut().enumerate() {
+            *x = i as u8;
+        }
+        pad.set_input(&input);
+
+        port.set_analog_mode(AnalogMode::Analog, false).unwrap();
+        let format = ResponseFormat::ANALOG
+            .with_pressure(PressureButton::Cross)
+            .with_pressure(PressureButton::R2);
+        port.set_response_format(format).unwrap();
+        assert_eq!(pad.response_format(), [0x3f, 0x10, 0x02]);
+
+        match port.read_input(None).unwrap() {
+            Device::DualShock2(x) => {
+                assert_eq!(x.lx, 4);
+                assert_eq!(x.pressure(PressureButton::Cross), Some(12));
+                assert_eq!(x.pressure(PressureButton::R2), Some(17));
+                assert_eq!(x.pressure(PressureButton::Square), None);
+            }
+            _ => panic!("expected a DualShock 2"),
+        }
+
+        // Buttons and a single pressure, no sticks
+        let format = ResponseFormat::DIGITAL.with_pressure(PressureButton::Up);
+        port.set_response_format(format).unwrap();
+        match port.read_input(None).unwrap() {
+            Device::DualShock2(x) => {
+                assert_eq!(x.lx, 0x80);
+                assert_eq!(x.pressure(PressureButton::Up), Some(8));
+            }
+            _ => panic!("expected a DualShock 2"),
+        }
+
+        // Changing modes puts the format back
+        port.set_analog_mode(AnalogMode::Analog, false).unwrap();
+        assert_eq!(port.response_format(), ResponseFormat::ANALOG);
+        assert!(matches!(port.read_input(None), Ok(Device::DualShock(_))));
+    }
+
+    #[test]
+    fn set_pressures() {
+        let pad = Simulator::new(Personality::DualShock2);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+        let mut input = [0u8; 18];
+
+        for (i, x) in input.iter_mut().enumerate() {
+            *x = i as u8;
+        }
+        pad.set_input(&input);
+
+        let buttons = [PressureButton::Cross, PressureButton::R2];
+        let format = port.set_pressures(&buttons).unwrap();
+        assert!(pad.is_analog());
+        assert!(!pad.in_config_mode());
+        assert_eq!(format, ResponseFormat::ANALOG.with_pressures(&buttons));
+        assert_eq!(port.query_response_format().unwrap(), format);
+        assert_eq!(pad.pressures(), 1 << 6 | 1 << 11);
+
+        match port.read_input(None).unwrap() {
+            Device::DualShock2(x) => {
+                assert_eq!(x.pressure(PressureButton::Cross), Some(12));
+                assert_eq!(x.pressure(PressureButton::R2), Some(17));
+                assert_eq!(x.pressure(PressureButton::Up), None);
+            }
+            _ => panic!("expected a DualShock 2"),
+        }
+
+        // Pressures that are sent but not measured read as released
+        port.set_response_format(format.with_pressure(PressureButton::Up))
+            .unwrap();
+        match port.read_input(None).unwrap() {
+            Device::DualShock2(x) => assert_eq!(x.pressure(PressureButton::Up), Some(0)),
+            _ => panic!("expected a DualShock 2"),
+        }
+
+        // The DualShock 1 doesn't measure pressure at all
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        assert!(matches!(
+            port.set_pressures(&buttons),
+            Err(Error::Unsupported)
+        ));
+        assert!(!pad.in_config_mode());
+    }
+
+    #[test]
+    fn actuator_map() {
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+        let rumble = ControlDS::new(true, 0x80);
+
+        // Big motor first, then the small one two slots later. Analog mode
+        // makes the poll long enough to reach it.
+        port.set_analog_mode(AnalogMode::Analog, false).unwrap();
+        let map = ActuatorMap::NONE.with(0, 1).with(2, 0);
+        port.set_actuators(map).unwrap();
+        assert_eq!(pad.actuators(), [0x01, 0xff, 0x00, 0xff, 0xff, 0xff]);
+        assert_eq!(port.actuators(), map);
+
+        port.read_input(Some(&rumble)).unwrap();
+        assert_eq!(pad.motors(), [0x80, 0x00, 0xff, 0x00, 0x00, 0x00]);
+
+        // No rumble at all
+        port.set_actuators(ActuatorMap::NONE).unwrap();
+        port.read_input(Some(&rumble)).unwrap();
+        assert_eq!(pad.motors(), [0x00; 6]);
+
+        // One slot per actuator the controller reports
+        let config = port.read_config().unwrap();
+        assert_eq!(ActuatorMap::from_config(&config), ActuatorMap::DUALSHOCK);
+
+        let pad = Simulator::new(Personality::JogCon);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+        let config = port.read_config().unwrap();
+        assert_eq!(ActuatorMap::from_config(&config), ActuatorMap::JOGCON);
+    }
+
+    #[test]
+    fn hotplug() {
+        let pad = Simulator::new(Personality::Missing);
+        let port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+        let mut connection = Connection::new(port, Setup::Pressure);
+
+        assert!(matches!(connection.poll(None), Ok((Device::None, None))));
+        assert!(!connection.connected());
+
+        pad.plug(Personality::DualShock2);
+        let (device, event) = connection.poll(None).unwrap();
+        assert!(matches!(device, Device::DualShock2(_)));
+        assert_eq!(event, Some(Event::Connected(DeviceId::DUALSHOCK2)));
+        assert_eq!(connection.poll(None).unwrap().1, None);
+
+        // Dropping back to digital mode gets it set up again
+        pad.press_analog_button();
+        let (device, event) = connection.poll(None).unwrap();
+        assert!(matches!(device, Device::DualShock2(_)));
+        assert_eq!(
+            event,
+            Some(Event::Changed {
+                from: DeviceId::DUALSHOCK2,
+                to: DeviceId::DIGITAL
+            })
+        );
+        assert_eq!(connection.id(), Some(DeviceId::DUALSHOCK2));
+
+        pad.plug(Personality::Missing);
+        assert!(matches!(
+            connection.poll(None),
+            Ok((Device::None, Some(Event::Disconnected)))
+        ));
+        assert_eq!(connection.port().response_format(), ResponseFormat::ANALOG);
+
+        // Controllers that can't be set up are used as they are
+        pad.plug(Personality::Classic);
+        let (device, event) = connection.poll(None).unwrap();
+        assert!(matches!(device, Device::Classic(_)));
+        assert_eq!(event, Some(Event::Connected(DeviceId::CONTROLLER)));
+
+        // Swapping one controller for another between polls
+        connection.set_setup(Setup::Custom(|port| {
+            port.set_analog_mode(AnalogMode::Analog, true)
+        }));
+        pad.plug(Personality::DualShock);
+        let (device, event) = connection.poll(None).unwrap();
+        assert!(matches!(device, Device::DualShock(_)));
+        assert_eq!(event, Some(Event::Connected(DeviceId::DUALSHOCK)));
+        assert!(pad.is_locked());
+    }
+
+    #[test]
+    fn analog_policy() {
+        let pad = Simulator::new(Personality::DualShock2);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+        let format = ResponseFormat::ANALOG.with_pressure(PressureButton::Cross);
+
+        port.set_pressures(&[PressureButton::Cross]).unwrap();
+        port.set_actuators(ActuatorMap::NONE.with(1, 0)).unwrap();
+
+        // Ignoring it, we're left with the buttons
+        pad.press_analog_button();
+        assert!(matches!(port.read_input(None), Ok(Device::Digital(_))));
+        assert_eq!(port.response_format(), ResponseFormat::ANALOG);
+
+        // Reporting it fails the one poll
+        port.set_analog_policy(AnalogPolicy::Report);
+        port.set_analog_mode(AnalogMode::Analog, false).unwrap();
+        pad.press_analog_button();
+        assert!(matches!(port.read_input(None), Err(Error::ModeChanged)));
+        assert!(matches!(port.read_input(None), Ok(Device::Digital(_))));
+
+        // Restoring it brings back everything it had
+        port.set_analog_policy(AnalogPolicy::Restore);
+        port.set_pressures(&[PressureButton::Cross]).unwrap();
+        pad.press_analog_button();
+        match port.read_input(None).unwrap() {
+            Device::DualShock2(x) => assert!(x.pressure(PressureButton::Cross).is_some()),
+            _ => panic!("expected a DualShock 2"),
+        }
+        assert!(pad.is_analog());
+        assert_eq!(port.response_format(), format);
+        assert_eq!(pad.pressures(), 1 << 6);
+        assert_eq!(pad.actuators(), [0xff, 0x00, 0xff, 0xff, 0xff, 0xff]);
+
+        // A hot-plug connection reports it as an event instead
+        let mut port = Connection::new(port, Setup::Pressure);
+        port.port().set_analog_policy(AnalogPolicy::Report);
+        assert_eq!(
+            port.poll(None).unwrap().1,
+            Some(Event::Connected(DeviceId::DUALSHOCK2))
+        );
+        pad.press_analog_button();
+        assert_eq!(port.poll(None).unwrap().1, Some(Event::ModeChanged));
+        assert_eq!(port.poll(None).unwrap().1, None);
+        assert_eq!(port.id(), Some(DeviceId::DIGITAL));
+    }
+
+    #[test]
+    fn stuck_in_escape_mode() {
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        port.set_analog_mode(AnalogMode::Analog, false).unwrap();
+
+        // A session that never got to clean up after itself, say from a
+        // reset halfway through, and the mode changing while it was stuck
+        core::mem::forget(port.config_session().unwrap());
+        pad.press_analog_button();
+        assert!(pad.in_config_mode());
+
+        assert!(matches!(port.read_input(None), Ok(Device::DualShock(_))));
+        assert!(!pad.in_config_mode());
+        assert!(pad.is_analog());
+        assert_eq!(port.escape_recoveries(), 1);
+
+        // Without analog mode to go back to it's just taken out
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        core::mem::forget(port.config_session().unwrap());
+        assert!(matches!(port.read_input(None), Ok(Device::Digital(_))));
+        assert!(!pad.is_analog());
+        assert_eq!(port.escape_recoveries(), 1);
+        assert!(matches!(port.read_input(None), Ok(Device::Digital(_))));
+        assert_eq!(port.escape_recoveries(), 1);
+    }
+
+    #[test]
+    fn simulated_read_config() {
+        let pad = Simulator::new(Personality::DualShock2);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        port.enable_pressure().unwrap();
+        let config = port.read_config().unwrap();
+        assert!(!pad.in_config_mode());
+
+        // Matches the dump of a real DualShock 2 in `research/README.md`
+        assert_eq!(config.status, [0x03, 0x02, 0x01, 0x02, 0x01, 0x00]);
+        assert_eq!(config.const1a, [0x00, 0x01, 0x02, 0x00, 0x0a]);
+        assert_eq!(config.const1b, [0x00, 0x01, 0x01, 0x01, 0x14]);
+        assert_eq!(config.const2, [0x00, 0x02, 0x00, 0x01, 0x00]);
+        assert_eq!(config.const3a, [0x00, 0x00, 0x04, 0x00, 0x00]);
+        assert_eq!(config.const3b, [0x00, 0x00, 0x07, 0x00, 0x00]);
+
+        assert_eq!(config.generation(), Generation::DualShock2);
+        assert!(config.analog());
+        assert_eq!(config.mode_count(), 2);
+        assert_eq!(config.mode_types(), [0x04, 0x07]);
+        assert_eq!(config.actuator_count(), 2);
+        assert_eq!(
+            config.actuator(1),
+            Some(Actuator {
+                kind: ActuatorKind::Variable,
+                current: 0x14
+            })
+        );
+        assert_eq!(config.actuator(2), None);
+        #[cfg(feature = "std")]
+        assert_eq!(format!("{}", config), "DualShock 2, analog on, 2 motors");
+
+        let pad = Simulator::new(Personality::JogCon);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        port.enable_jogcon().unwrap();
+        let config = port.read_config().unwrap();
+        assert_eq!(config.status, [0x01, 0x02, 0x01, 0x01, 0x01, 0x00]);
+        assert_eq!(config.const1a, [0x00, 0x04, 0x03, 0x01, 0x1e]);
+        assert_eq!(config.const2, [0x00, 0x01, 0x00, 0x00, 0x00]);
+
+        assert_eq!(config.generation(), Generation::DualShock);
+        assert_eq!(config.model(), Some(Model::JogCon));
+        assert_eq!(config.mode_count(), 2);
+        assert_eq!(config.actuator(0).unwrap().kind, ActuatorKind::Variable);
+        assert_eq!(config.actuator(1), None);
+        #[cfg(feature = "std")]
+        assert_eq!(format!("{}", config), "JogCon, analog on, 1 motor");
+
+        // Says it's a DualShock too, but its second mode is analog
+        let pad = Simulator::new(Personality::GuitarHero);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        let config = port.read_config().unwrap();
+        assert_eq!(config.generation(), Generation::DualShock);
+        assert_eq!(config.model(), Some(Model::GuitarHero));
+        #[cfg(feature = "std")]
+        assert_eq!(format!("{}", config), "Guitar Hero, analog off, 2 motors");
+    }
+
+    #[test]
+    fn protocol_errors() {
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        pad.set_fault(Some(Fault::NoAck));
+        assert!(matches!(port.read_input(None), Err(Error::BadResponse)));
+
+        pad.set_fault(Some(Fault::Collision));
+        assert!(matches!(port.read_input(None), Err(Error::LateCollision)));
+
+        pad.set_fault(None);
+        assert!(port.read_input(None).is_ok());
+
+        // Pulled out part way through configuring isn't the same as not
+        // knowing the command
+        let mut session = port.config_session().unwrap();
+        pad.plug(Personality::Missing);
+        assert!(matches!(session.read_status(), Err(Error::NotPresent)));
+        drop(session);
+        pad.plug(Personality::DualShock);
+
+        // No escape mode on the original controller
+        pad.plug(Personality::Classic);
+        assert!(matches!(port.enable_pressure(), Err(Error::Unsupported)));
+        assert!(matches!(port.read_config(), Err(Error::Unsupported)));
+
+        pad.plug(Personality::NegCon);
+        assert!(matches!(port.enable_jogcon(), Err(Error::Unsupported)));
+
+        pad.plug(Personality::Missing);
+        assert!(matches!(port.enable_pressure(), Err(Error::NotPresent)));
+    }
+
+    #[test]
+    fn poll_length() {
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        port.enable_pressure().unwrap();
+
+        // Header plus three words
+        let before = pad.clocked();
+        let data = port.read_raw(None).unwrap();
+        assert_eq!(pad.clocked() - before, 9);
+        assert_eq!(data.as_bytes().len(), 6);
+        assert_eq!(data.data[5], 0x80);
+        assert!(data.data[6..].iter().all(|x| *x == 0));
+
+        // Without a select line we can't stop early, but the tail is still cleared
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::<_, SimulatedSelect>::new(pad.bus(), None);
+
+        let before = pad.clocked();
+        let data = port.read_raw(None).unwrap();
+        assert_eq!(pad.clocked() - before, 35);
+        assert_eq!(data.as_bytes(), &[0xff, 0xff]);
+        assert!(data.data[2..].iter().all(|x| *x == 0));
+    }
+
+    #[test]
+    fn device_id() {
+        let pad = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        // A DualShock in digital mode sends what the original Controller does,
+        // but doesn't get mistaken for one
+        assert!(matches!(port.read_input(None), Ok(Device::Digital(_))));
+        assert_eq!(DeviceId::DIGITAL.kind(), DeviceType::Digital);
+        assert_eq!(DeviceId::DIGITAL.words(), 1);
+
+        pad.plug(Personality::Classic);
+        assert!(matches!(port.read_input(None), Ok(Device::Classic(_))));
+        assert_eq!(DeviceId::CONTROLLER.kind(), DeviceType::Controller);
+
+        // The Analog Controller's green mode isn't a DualShock either
+        let green = ControllerData::new(&[0xff; 6]);
+        assert!(matches!(
+            port.decode_input(DeviceId::ANALOG_GREEN, &green),
+            Ok(Device::AnalogGreen(_))
+        ));
+
+        // Things we can't decode still come back with what they sent
+        pad.plug(Personality::Multitap);
+        match port.read_input(None).unwrap() {
+            Device::Unknown { id, data } => {
+                assert_eq!(id, DeviceId::MULTITAP);
+                assert_eq!(id.kind(), DeviceType::Multitap);
+                assert_eq!(data.as_bytes().len(), 32);
+            }
+            _ => panic!("expected an unknown device"),
+        }
+
+        assert_eq!(DeviceId::new(0x31).kind(), DeviceType::KonamiLightgun);
+        assert_eq!(DeviceId::new(0x96).kind(), DeviceType::Keyboard);
+        assert_eq!(DeviceId::new(0x96).length(), 12);
+        assert_eq!(DeviceId::new(0xb2).kind(), DeviceType::Other(0xb));
+        assert_eq!(DeviceId::MULTITAP.length(), 32);
+        assert!(!DeviceId::NOT_PRESENT.present());
+    }
+
+    #[test]
+    fn decoders() {
+        static GUITAR: GuitarHeroDecoder = GuitarHeroDecoder;
+        static MULTITAP: Custom<Classic> = Custom::new(7, &[DeviceId::MULTITAP]);
+        static SELECT: HoldingSelect = HoldingSelect;
+
+        // Only takes the original controller while Select is held, then wants
+        // more than it sends
+        struct HoldingSelect;
+
+        impl Decoder for HoldingSelect {
+            fn claims(&self, id: DeviceId, data: &ControllerData) -> bool {
+                id == DeviceId::CONTROLLER && data.as_bytes()[0] & 0x01 == 0
+            }
+
+            fn decode(&self, _: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+                Err(DecodeError {
+                    expected: 4,
+                    actual: data.as_bytes().len(),
+                })
+            }
+        }
+
+        let pad = Simulator::new(Personality::GuitarHero);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+
+        // The Guitar Hero controller passes for a DualShock until we say otherwise
+        assert!(matches!(port.read_input(None), Ok(Device::DualShock(_))));
+        port.add_decoder(&GUITAR).unwrap();
+        assert!(matches!(port.read_input(None), Ok(Device::GuitarHero(_))));
+
+        port.add_decoder(&MULTITAP).unwrap();
+        pad.plug(Personality::Multitap);
+        let device = port.read_input(None).unwrap();
+        assert!(matches!(device, Device::Custom(x) if (x.tag, x.id) == (7, DeviceId::MULTITAP)));
+        let buttons = MULTITAP.get(&device).unwrap().unwrap();
+        assert!(!buttons.buttons.cross());
+
+        // Decoders can go by what was sent too, and their errors come back as is
+        port.clear_decoders();
+        port.add_decoder(&SELECT).unwrap();
+        pad.plug(Personality::Classic);
+        assert!(matches!(port.read_input(None), Ok(Device::Classic(_))));
+        pad.set_input(&[0xfe, 0xff]);
+        assert!(matches!(
+            port.read_input(None),
+            Err(Error::Decode(DecodeError {
+                expected: 4,
+                actual: 2
+            }))
+        ));
+        port.add_decoder(&GUITAR).unwrap();
+
+        // Only so many fit
+        for _ in 2..MAX_DECODERS {
+            port.add_decoder(&GUITAR).unwrap();
+        }
+        assert!(matches!(port.add_decoder(&GUITAR), Err(Error::NoSpace)));
+
+        port.clear_decoders();
+        pad.plug(Personality::Multitap);
+        assert!(matches!(port.read_input(None), Ok(Device::Unknown { .. })));
+    }
+
+    #[test]
+    fn poll_bad_length() {
+        let pad = Simulator::new(Personality::DualShock2);
+        let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+        let mut buffer = [0u8; 9];
+
+        port.enable_pressure().unwrap();
+
+        // Ask for the buttons and only one stick, which the library doesn't expect
+        port.send_command(&[0x00, 0x43, 0x00, 0x01], &mut buffer)
+            .unwrap();
+        port.send_command(&[0x00, 0x4f, 0x00, 0x0f, 0x00, 0x00], &mut buffer)
+            .unwrap();
+        port.send_command(&[0x00, 0x43, 0x00, 0x00], &mut buffer)
+            .unwrap();
+
+        assert!(matches!(port.read_input(None), Err(Error::BadLength)));
+    }
+
+    #[test]
+    fn memory_card() {
+        let sim = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+        let mut frame = [0u8; FRAME_SIZE];
+
+        sim.insert_card();
+        let mut card = port.memory_card();
+
+        let id = card.read_id().unwrap();
+        assert_eq!(id.frames, 1024);
+        assert_eq!(id.frame_size, 128);
+        assert_eq!(card.flags().unwrap(), Flags::FRESH);
+
+        let mut data = [0u8; FRAME_SIZE];
+        data[..2].copy_from_slice(b"MC");
+        data[127] = 0x0e;
+
+        // Writing is what tells the card it's been noticed
+        assert_eq!(card.write_frame(0x3ff, &data).unwrap(), Flags::FRESH);
+        assert_eq!(card.read_frame(0x3ff, &mut frame).unwrap(), Flags::empty());
+        assert_eq!(frame[..], data[..]);
+        assert_eq!(sim.card_frame(0x3ff)[..], data[..]);
+
+        // The controller is still there on the same bus
+        assert!(matches!(port.read_input(None), Ok(Device::Digital(_))));
+    }
+
+    #[test]
+    fn memory_card_errors() {
+        let sim = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+        let mut frame = [0u8; FRAME_SIZE];
+
+        let mut card = port.memory_card();
+        assert!(matches!(card.read_id(), Err(Error::NotPresent)));
+
+        sim.insert_card();
+        let mut card = port.memory_card();
+        assert!(matches!(
+            card.read_frame(1024, &mut frame),
+            Err(Error::BadSector)
+        ));
+        assert!(matches!(
+            card.write_frame(1024, &frame),
+            Err(Error::BadSector)
+        ));
+        assert_eq!(sim.card_flags(), Flags::FRESH | Flags::WRITE_ERROR);
+
+        // There's no card behind the second port of a multi-tap
+        port.set_multitap_port(MultitapPort::B);
+        let mut card = port.memory_card();
+        assert!(matches!(
+            card.read_frame(0, &mut frame),
+            Err(Error::NotPresent)
+        ));
+    }
+
+    #[test]
+    fn pocketstation() {
+        let sim = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+
+        sim.insert_card();
+        assert!(matches!(port.pocketstation(), Err(Error::Unsupported)));
+
+        sim.insert_pocketstation();
+        let mut pocket = port.pocketstation().unwrap();
+
+        let status = pocket.status().unwrap();
+        assert_eq!(status.serial, 0x1234_5678);
+        assert_eq!(
+            status.date,
+            Date {
+                year: 2026,
+                month: 10,
+                day: 18
+            }
+        );
+        assert_eq!(
+            status.time,
+            Time {
+                hour: 12,
+                minute: 30,
+                second: 45,
+                weekday: 0
+            }
+        );
+        assert_eq!(pocket.application().unwrap(), None);
+
+        pocket.launch(3, 0xdead_beef).unwrap();
+        assert_eq!(sim.pocketstation_launched(), Some((3, 0xdead_beef)));
+        assert_eq!(pocket.application().unwrap(), Some(3));
     }
 }
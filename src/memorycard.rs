@@ -0,0 +1,301 @@
+//! PlayStation Memory Card
+//! ============================
+//! The memory card shares the bus with the controller in the same slot but
+//! answers to its own address. It stores 128 KiB split into 1024 frames of
+//! 128 bytes each, and the only things it knows how to do are read a frame,
+//! write a frame and tell you how big it is.
+//!
+//! Frames are raw here. The `filesystem` module makes sense of them as saves,
+//! over anything that implements `FrameStore`, the `header` module shows what
+//! each save is, the `image` module handles the files emulators keep cards in,
+//! the `sync` module keeps a card and an image in step, and the `verify` module
+//! checks for and fixes damage.
+
+pub mod filesystem;
+pub mod header;
+pub mod image;
+mod maintenance;
+pub mod sync;
+pub mod verify;
+
+use super::{Error, PlayStationPort, BUS_IDLE};
+use core::convert::Infallible;
+use hal::digital::OutputPin;
+use hal::spi::SpiBus;
+
+/// Bytes in a single frame
+pub const FRAME_SIZE: usize = 128;
+/// Frames on a standard PlayStation memory card
+pub const FRAME_COUNT: u16 = 1024;
+/// Bytes on a standard PlayStation memory card
+pub const CARD_SIZE: usize = FRAME_SIZE * FRAME_COUNT as usize;
+
+/// A single frame of memory card data
+pub type Frame = [u8; FRAME_SIZE];
+
+// The memory card's address is the multi-tap port with the high bit set
+const CARD_ADDRESS: u8 = 0x80;
+
+const CMD_READ: u8 = 0x52;
+const CMD_GET_ID: u8 = 0x53;
+const CMD_WRITE: u8 = 0x57;
+
+const CARD_ID: &[u8] = &[0x5a, 0x5d];
+const COMMAND_ACK: &[u8] = &[0x5c, 0x5d];
+
+const END_GOOD: u8 = 0x47;
+const END_BAD_CHECKSUM: u8 = 0x4e;
+const END_BAD_SECTOR: u8 = 0xff;
+
+const READ_LENGTH: usize = 140;
+const WRITE_LENGTH: usize = 138;
+const GET_ID_LENGTH: usize = 10;
+
+bitflags::bitflags! {
+    /// The FLAG byte the memory card sends back with every command
+    pub struct Flags: u8 {
+        /// The last write failed
+        const WRITE_ERROR = 0x04;
+        /// The card was inserted since the last successful write. Games use
+        /// this to notice the card was swapped, so it stays set through reads.
+        const FRESH = 0x08;
+    }
+}
+
+/// Sizes the memory card reports for itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CardId {
+    /// Number of frames on the card
+    pub frames: u16,
+    /// Bytes per frame
+    pub frame_size: u16,
+}
+
+/// A memory card sitting on a `PlayStationPort`. Borrow one from the port with
+/// `PlayStationPort::memory_card`.
+pub struct MemoryCard<'a, SPI, CS> {
+    port: &'a mut PlayStationPort<SPI, CS>,
+}
+
+impl<'a, SPI, CS> MemoryCard<'a, SPI, CS>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+{
+    pub(crate) fn new(port: &'a mut PlayStationPort<SPI, CS>) -> Self {
+        Self { port }
+    }
+
+    /// XOR of the frame address and every byte of the frame
+    fn checksum(frame: u16, data: &[u8]) -> u8 {
+        let [msb, lsb] = frame.to_be_bytes();
+
+        data.iter().fold(msb ^ lsb, |a, x| a ^ x)
+    }
+
+    /// Send a command to the card and check it knows what we're on about
+    pub(crate) fn transact(
+        &mut self,
+        command: &[u8],
+        result: &mut [u8],
+    ) -> Result<Flags, Error<SPI::Error>> {
+        let address = CARD_ADDRESS | self.port.multitap_port.clone() as u8;
+
+        self.port.send_to(address, command, result)?;
+
+        if result[0] != BUS_IDLE {
+            return Err(Error::LateCollision);
+        }
+
+        // Without a card nothing drives the bus at all
+        if result[2..4].iter().all(|x| *x == BUS_IDLE) {
+            return Err(Error::NotPresent);
+        }
+
+        if &result[2..4] != CARD_ID {
+            return Err(Error::BadResponse);
+        }
+
+        Ok(Flags::from_bits_truncate(result[1]))
+    }
+
+    /// Read a frame from the card into `data`. If it fails with `BadChecksum`,
+    /// `data` still holds what the card sent.
+    pub fn read_frame(&mut self, frame: u16, data: &mut Frame) -> Result<Flags, Error<SPI::Error>> {
+        let [msb, lsb] = frame.to_be_bytes();
+        let mut buffer = [0u8; READ_LENGTH];
+
+        let flags = self.transact(&[0, CMD_READ, 0, 0, msb, lsb], &mut buffer)?;
+
+        if &buffer[6..8] != COMMAND_ACK {
+            return Err(Error::BadResponse);
+        }
+
+        // The card confirms the address, or sends 0xFFFF if it won't read it
+        if buffer[8..10] != [msb, lsb] {
+            return Err(Error::BadSector);
+        }
+
+        data.copy_from_slice(&buffer[10..138]);
+
+        if Self::checksum(frame, &buffer[10..138]) != buffer[138] {
+            return Err(Error::BadChecksum);
+        }
+
+        if buffer[139] != END_GOOD {
+            return Err(Error::BadResponse);
+        }
+
+        Ok(flags)
+    }
+
+    /// Write `data` to a frame on the card
+    pub fn write_frame(&mut self, frame: u16, data: &Frame) -> Result<Flags, Error<SPI::Error>> {
+        let [msb, lsb] = frame.to_be_bytes();
+        let mut buffer = [0u8; WRITE_LENGTH];
+
+        buffer[1] = CMD_WRITE;
+        buffer[4] = msb;
+        buffer[5] = lsb;
+        buffer[6..134].copy_from_slice(data);
+        buffer[134] = Self::checksum(frame, data);
+
+        let command = buffer;
+        let flags = self.transact(&command, &mut buffer)?;
+
+        if &buffer[135..137] != COMMAND_ACK {
+            return Err(Error::BadResponse);
+        }
+
+        match buffer[137] {
+            END_GOOD => Ok(flags),
+            END_BAD_CHECKSUM => Err(Error::BadChecksum),
+            END_BAD_SECTOR => Err(Error::BadSector),
+            _ => Err(Error::BadResponse),
+        }
+    }
+
+    /// Ask the card how big it is
+    pub fn read_id(&mut self) -> Result<CardId, Error<SPI::Error>> {
+        let mut buffer = [0u8; GET_ID_LENGTH];
+
+        self.transact(&[0, CMD_GET_ID], &mut buffer)?;
+
+        if &buffer[4..6] != COMMAND_ACK {
+            return Err(Error::BadResponse);
+        }
+
+        Ok(CardId {
+            frames: u16::from_be_bytes([buffer[6], buffer[7]]),
+            frame_size: u16::from_be_bytes([buffer[8], buffer[9]]),
+        })
+    }
+
+    /// Read the card's FLAG byte without touching its contents
+    pub fn flags(&mut self) -> Result<Flags, Error<SPI::Error>> {
+        let mut buffer = [0u8; GET_ID_LENGTH];
+
+        self.transact(&[0, CMD_GET_ID], &mut buffer)
+    }
+}
+
+/// Anything that holds memory card frames, be it a real card or a copy of one
+/// kept in memory
+pub trait FrameStore {
+    /// The error of whatever is underneath, such as the SPI bus
+    type Error;
+
+    /// Read a frame into `data`. On `BadChecksum`, `data` holds what was read
+    /// anyway.
+    fn read_frame(&mut self, frame: u16, data: &mut Frame) -> Result<(), Error<Self::Error>>;
+
+    /// Write `data` to a frame
+    fn write_frame(&mut self, frame: u16, data: &Frame) -> Result<(), Error<Self::Error>>;
+}
+
+impl<'a, SPI, CS> FrameStore for MemoryCard<'a, SPI, CS>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+{
+    type Error = SPI::Error;
+
+    fn read_frame(&mut self, frame: u16, data: &mut Frame) -> Result<(), Error<SPI::Error>> {
+        MemoryCard::read_frame(self, frame, data).map(|_| ())
+    }
+
+    fn write_frame(&mut self, frame: u16, data: &Frame) -> Result<(), Error<SPI::Error>> {
+        MemoryCard::write_frame(self, frame, data).map(|_| ())
+    }
+}
+
+/// A whole card's worth of frames in memory
+impl FrameStore for [u8; CARD_SIZE] {
+    type Error = Infallible;
+
+    fn read_frame(&mut self, frame: u16, data: &mut Frame) -> Result<(), Error<Infallible>> {
+        let offset = usize::from(frame) * FRAME_SIZE;
+
+        if frame >= FRAME_COUNT {
+            return Err(Error::BadSector);
+        }
+
+        data.copy_from_slice(&self[offset..offset + FRAME_SIZE]);
+
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: u16, data: &Frame) -> Result<(), Error<Infallible>> {
+        let offset = usize::from(frame) * FRAME_SIZE;
+
+        if frame >= FRAME_COUNT {
+            return Err(Error::BadSector);
+        }
+
+        self[offset..offset + FRAME_SIZE].copy_from_slice(data);
+
+        Ok(())
+    }
+}
+
+/// A `FrameStore` whose errors fit in with those of `E`, so saves can be moved
+/// between two stores that fail in different ways. A copy of a card kept in
+/// memory can't fail at the bus level, so it fits in with anything.
+pub trait Widen<E>: FrameStore {
+    /// Turn one of this store's errors into the wider kind
+    fn widen(error: Error<Self::Error>) -> Error<E>;
+}
+
+impl<'a, SPI, CS> Widen<SPI::Error> for MemoryCard<'a, SPI, CS>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+{
+    fn widen(error: Error<SPI::Error>) -> Error<SPI::Error> {
+        error
+    }
+}
+
+impl<E> Widen<E> for [u8; CARD_SIZE] {
+    fn widen(error: Error<Infallible>) -> Error<E> {
+        error.widen()
+    }
+}
+
+impl<E, T: Widen<E> + ?Sized> Widen<E> for &mut T {
+    fn widen(error: Error<T::Error>) -> Error<E> {
+        T::widen(error)
+    }
+}
+
+impl<T: FrameStore + ?Sized> FrameStore for &mut T {
+    type Error = T::Error;
+
+    fn read_frame(&mut self, frame: u16, data: &mut Frame) -> Result<(), Error<T::Error>> {
+        (**self).read_frame(frame, data)
+    }
+
+    fn write_frame(&mut self, frame: u16, data: &Frame) -> Result<(), Error<T::Error>> {
+        (**self).write_frame(frame, data)
+    }
+}
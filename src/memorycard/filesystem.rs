@@ -0,0 +1,689 @@
+//! PlayStation Memory Card Filesystem
+//! ============================
+//! The card is split into 16 blocks of 64 frames. Block 0 is all bookkeeping and
+//! the other 15 hold saves:
+//!
+//! * Frame 0 is the header, "MC" followed by zeros and an XOR checksum
+//! * Frames 1 to 15 are the directory, one entry for each of the 15 save blocks
+//! * Frames 16 to 35 list frames that went bad, and frames 36 to 55 stand in for them
+//! * Frame 63 is a copy of the header
+//!
+//! A save longer than a block is a chain of directory entries, each pointing at
+//! the next. Only the first entry of the chain has the save's size and filename.
+//!
+//! ```
+//! use pscontroller_rs::memorycard::filesystem::Filesystem;
+//! use pscontroller_rs::memorycard::CARD_SIZE;
+//!
+//! let mut image = [0u8; CARD_SIZE];
+//! let mut card = Filesystem::format(&mut image).unwrap();
+//! let block = card.write_save(b"BASLUS-00000SAVE", &[0x55; 9000]).unwrap();
+//!
+//! for save in card.saves() {
+//!     assert_eq!(save.filename(), b"BASLUS-00000SAVE");
+//!     assert_eq!(save.size(), 16384);
+//! }
+//!
+//! let mut data = [0u8; 16384];
+//! card.read_save(block, &mut data).unwrap();
+//! assert_eq!(data[8999], 0x55);
+//! ```
+
+use super::{Frame, FrameStore, FRAME_SIZE};
+use crate::Error;
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Frames in a block
+pub const FRAMES_PER_BLOCK: u16 = 64;
+/// Bytes in a block
+pub const BLOCK_SIZE: usize = FRAME_SIZE * FRAMES_PER_BLOCK as usize;
+/// Blocks available for saves, which is all of them but the first
+pub const SAVE_BLOCKS: usize = 15;
+/// Longest filename a directory entry can hold
+pub const FILENAME_LENGTH: usize = 20;
+
+pub(crate) const HEADER_MAGIC: &[u8] = b"MC";
+pub(crate) const DIRECTORY_FRAME: u16 = 1;
+pub(crate) const BROKEN_LIST_FRAME: u16 = 16;
+pub(crate) const HEADER_COPY_FRAME: u16 = 63;
+pub(crate) const REPLACEMENT_FRAME: u16 = 36;
+pub(crate) const BROKEN_COUNT: usize = 20;
+pub(crate) const NO_BROKEN_FRAME: u32 = 0xffff_ffff;
+const NO_NEXT_BLOCK: u16 = 0xffff;
+
+const FILENAME_OFFSET: usize = 0x0a;
+pub(crate) const CHECKSUM_OFFSET: usize = FRAME_SIZE - 1;
+
+/// XOR of every byte of a frame but the last, which is where it gets stored
+pub(crate) fn checksum(frame: &[u8]) -> u8 {
+    frame[..CHECKSUM_OFFSET].iter().fold(0, |a, x| a ^ x)
+}
+
+/// What a block is being used for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockState {
+    /// Nothing's there
+    Free,
+    /// The first block of a save
+    First,
+    /// A block in the middle of a save
+    Middle,
+    /// The last block of a save longer than one block
+    Last,
+    /// The first block of a deleted save
+    DeletedFirst,
+    /// A middle block of a deleted save
+    DeletedMiddle,
+    /// The last block of a deleted save
+    DeletedLast,
+    /// Anything else. The block shouldn't be touched.
+    Unusable,
+}
+
+impl BlockState {
+    fn from_u32(state: u32) -> Self {
+        match state {
+            0xa0 => BlockState::Free,
+            0x51 => BlockState::First,
+            0x52 => BlockState::Middle,
+            0x53 => BlockState::Last,
+            0xa1 => BlockState::DeletedFirst,
+            0xa2 => BlockState::DeletedMiddle,
+            0xa3 => BlockState::DeletedLast,
+            _ => BlockState::Unusable,
+        }
+    }
+
+    fn to_u32(self) -> u32 {
+        match self {
+            BlockState::Free => 0xa0,
+            BlockState::First => 0x51,
+            BlockState::Middle => 0x52,
+            BlockState::Last => 0x53,
+            BlockState::DeletedFirst => 0xa1,
+            BlockState::DeletedMiddle => 0xa2,
+            BlockState::DeletedLast => 0xa3,
+            BlockState::Unusable => 0xff,
+        }
+    }
+
+    /// Whether the block belongs to a save that hasn't been deleted
+    pub fn in_use(self) -> bool {
+        matches!(
+            self,
+            BlockState::First | BlockState::Middle | BlockState::Last
+        )
+    }
+
+    /// Whether the block belongs to a deleted save. Its data is still there
+    /// until the block is reused.
+    pub fn deleted(self) -> bool {
+        matches!(
+            self,
+            BlockState::DeletedFirst | BlockState::DeletedMiddle | BlockState::DeletedLast
+        )
+    }
+}
+
+/// A directory frame, describing one block
+#[derive(Clone, Copy)]
+pub struct DirectoryEntry {
+    data: Frame,
+}
+
+impl DirectoryEntry {
+    /// An entry for a block with nothing in it
+    pub(crate) fn free() -> Self {
+        let mut entry = Self {
+            data: [0u8; FRAME_SIZE],
+        };
+
+        entry.set_state(BlockState::Free);
+        entry.set_next(None);
+        entry
+    }
+
+    /// An entry as it was read from the card
+    pub(crate) fn from_frame(data: Frame) -> Self {
+        Self { data }
+    }
+
+    /// What the block is being used for
+    pub fn state(&self) -> BlockState {
+        BlockState::from_u32(LittleEndian::read_u32(&self.data[0..4]))
+    }
+
+    /// Size of the whole save in bytes. Only the first block of a save has this.
+    pub fn size(&self) -> u32 {
+        LittleEndian::read_u32(&self.data[4..8])
+    }
+
+    /// The next block of the save, if there is one
+    pub fn next(&self) -> Option<u8> {
+        match LittleEndian::read_u16(&self.data[8..10]) {
+            NO_NEXT_BLOCK => None,
+            // Anything past the last block comes out as 255, which isn't a block either
+            x => Some(core::cmp::min(x, 0xfe) as u8 + 1),
+        }
+    }
+
+    /// The save's filename, without the trailing zeros. The first two characters
+    /// are the region, then the product code, then whatever the game likes.
+    pub fn filename(&self) -> &[u8] {
+        let name = &self.data[FILENAME_OFFSET..FILENAME_OFFSET + FILENAME_LENGTH];
+        let length = name.iter().position(|x| *x == 0).unwrap_or(name.len());
+
+        &name[..length]
+    }
+
+    /// The region the save's game is from, like `BA` for America or `BI` for Japan
+    pub fn region(&self) -> &[u8] {
+        self.filename().get(..2).unwrap_or(&[])
+    }
+
+    /// The game's product code, like `SCUS-94228`
+    pub fn product_code(&self) -> &[u8] {
+        let name = self.filename();
+
+        name.get(2..12)
+            .unwrap_or_else(|| name.get(2..).unwrap_or(&[]))
+    }
+
+    /// Whether the entry's checksum matches the rest of it
+    pub fn checksum_valid(&self) -> bool {
+        checksum(&self.data) == self.data[CHECKSUM_OFFSET]
+    }
+
+    /// The entry as it's stored on the card
+    pub fn as_bytes(&self) -> &Frame {
+        &self.data
+    }
+
+    pub(crate) fn set_state(&mut self, state: BlockState) {
+        LittleEndian::write_u32(&mut self.data[0..4], state.to_u32());
+        self.seal();
+    }
+
+    pub(crate) fn set_size(&mut self, size: u32) {
+        LittleEndian::write_u32(&mut self.data[4..8], size);
+        self.seal();
+    }
+
+    pub(crate) fn set_next(&mut self, next: Option<u8>) {
+        let next = next.map_or(NO_NEXT_BLOCK, |x| u16::from(x) - 1);
+
+        LittleEndian::write_u16(&mut self.data[8..10], next);
+        self.seal();
+    }
+
+    pub(crate) fn set_filename(&mut self, filename: &[u8]) {
+        let name = &mut self.data[FILENAME_OFFSET..FILENAME_OFFSET + FILENAME_LENGTH];
+
+        for x in name.iter_mut() {
+            *x = 0;
+        }
+        name[..filename.len()].copy_from_slice(filename);
+        self.seal();
+    }
+
+    fn seal(&mut self) {
+        self.data[CHECKSUM_OFFSET] = checksum(&self.data);
+    }
+}
+
+/// The blocks a save is stored in, in order
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Chain {
+    blocks: [u8; SAVE_BLOCKS],
+    length: usize,
+}
+
+impl Chain {
+    fn new() -> Self {
+        Self {
+            blocks: [0u8; SAVE_BLOCKS],
+            length: 0,
+        }
+    }
+
+    fn push(&mut self, block: u8) {
+        self.blocks[self.length] = block;
+        self.length += 1;
+    }
+
+    /// The block numbers, from 1 to 15
+    pub fn as_slice(&self) -> &[u8] {
+        &self.blocks[..self.length]
+    }
+
+    /// How many blocks the save takes up
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Whether there are no blocks at all
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+/// A save on the card
+#[derive(Clone, Copy)]
+pub struct Save<'a> {
+    block: u8,
+    entry: &'a DirectoryEntry,
+}
+
+impl<'a> Save<'a> {
+    /// The first block of the save, which is how the rest of the filesystem
+    /// refers to it
+    pub fn block(&self) -> u8 {
+        self.block
+    }
+
+    /// The save's filename
+    pub fn filename(&self) -> &'a [u8] {
+        self.entry.filename()
+    }
+
+    /// Size of the save in bytes
+    pub fn size(&self) -> u32 {
+        self.entry.size()
+    }
+
+    /// The directory entry for the first block
+    pub fn entry(&self) -> &'a DirectoryEntry {
+        self.entry
+    }
+}
+
+/// Iterator over the saves on a card
+pub struct Saves<'a> {
+    entries: &'a [DirectoryEntry; SAVE_BLOCKS],
+    index: usize,
+}
+
+impl<'a> Iterator for Saves<'a> {
+    type Item = Save<'a>;
+
+    fn next(&mut self) -> Option<Save<'a>> {
+        while self.index < SAVE_BLOCKS {
+            let entry = &self.entries[self.index];
+            self.index += 1;
+
+            if entry.state() == BlockState::First {
+                return Some(Save {
+                    block: self.index as u8,
+                    entry,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+/// A memory card's worth of saves, over anything that holds frames
+pub struct Filesystem<S> {
+    pub(crate) store: S,
+    pub(crate) entries: [DirectoryEntry; SAVE_BLOCKS],
+    pub(crate) broken: [u32; BROKEN_COUNT],
+}
+
+impl<S: FrameStore> Filesystem<S> {
+    /// Read the header, directory and broken frame list from a card
+    pub fn open(mut store: S) -> Result<Self, Error<S::Error>> {
+        let mut frame = [0u8; FRAME_SIZE];
+
+        store.read_frame(0, &mut frame)?;
+        if &frame[..2] != HEADER_MAGIC {
+            return Err(Error::Corrupt);
+        }
+
+        let mut entries = [DirectoryEntry::free(); SAVE_BLOCKS];
+        for (x, entry) in entries.iter_mut().enumerate() {
+            store.read_frame(DIRECTORY_FRAME + x as u16, &mut entry.data)?;
+        }
+
+        let mut broken = [NO_BROKEN_FRAME; BROKEN_COUNT];
+        for (x, broken) in broken.iter_mut().enumerate() {
+            store.read_frame(BROKEN_LIST_FRAME + x as u16, &mut frame)?;
+            *broken = LittleEndian::read_u32(&frame[0..4]);
+        }
+
+        Ok(Self {
+            store,
+            entries,
+            broken,
+        })
+    }
+
+    /// Give back whatever the frames are stored in
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// The directory entry for a block, from 1 to 15
+    pub fn entry(&self, block: u8) -> Option<&DirectoryEntry> {
+        self.entries.get(usize::from(block).wrapping_sub(1))
+    }
+
+    /// All the saves on the card
+    pub fn saves(&self) -> Saves<'_> {
+        Saves {
+            entries: &self.entries,
+            index: 0,
+        }
+    }
+
+    /// Look for a save by its filename
+    pub fn find(&self, filename: &[u8]) -> Option<u8> {
+        self.saves()
+            .find(|x| x.filename() == filename)
+            .map(|x| x.block())
+    }
+
+    /// Blocks that aren't being used by anything
+    pub fn free_blocks(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|x| !x.state().in_use() && x.state() != BlockState::Unusable)
+            .count()
+    }
+
+    /// Follow the links of a save starting at `first`. Deleted saves can be
+    /// followed as well, as long as none of their blocks have been reused.
+    pub fn chain(&self, first: u8) -> Result<Chain, Error<S::Error>> {
+        let entry = self.entry(first).ok_or(Error::NotFound)?;
+        let deleted = match entry.state() {
+            BlockState::First => false,
+            BlockState::DeletedFirst => true,
+            _ => return Err(Error::NotFound),
+        };
+
+        let mut chain = Chain::new();
+        let mut next = Some(first);
+
+        while let Some(block) = next {
+            if chain.as_slice().contains(&block) || chain.len() == SAVE_BLOCKS {
+                return Err(Error::Corrupt);
+            }
+
+            let entry = self.entry(block).ok_or(Error::Corrupt)?;
+            let expected = match (chain.is_empty(), entry.state()) {
+                (true, _) => true,
+                (false, BlockState::Middle) | (false, BlockState::Last) => !deleted,
+                (false, BlockState::DeletedMiddle) | (false, BlockState::DeletedLast) => deleted,
+                _ => false,
+            };
+
+            if !expected {
+                return Err(Error::Corrupt);
+            }
+
+            chain.push(block);
+            next = entry.next();
+        }
+
+        Ok(chain)
+    }
+
+    /// The frame that's actually used in place of `frame`, in case it went bad
+    /// and was moved
+    fn remap(&self, frame: u16) -> u16 {
+        match self.broken.iter().position(|x| *x == u32::from(frame)) {
+            Some(x) => REPLACEMENT_FRAME + x as u16,
+            None => frame,
+        }
+    }
+
+    pub(crate) fn read(&mut self, frame: u16, data: &mut Frame) -> Result<(), Error<S::Error>> {
+        let frame = self.remap(frame);
+
+        self.store.read_frame(frame, data)
+    }
+
+    pub(crate) fn write(&mut self, frame: u16, data: &Frame) -> Result<(), Error<S::Error>> {
+        let frame = self.remap(frame);
+
+        self.store.write_frame(frame, data)
+    }
+
+    /// Read a save into `buffer`, returning how many bytes it was. The buffer has
+    /// to be big enough for every block of the save.
+    pub fn read_save(&mut self, first: u8, buffer: &mut [u8]) -> Result<usize, Error<S::Error>> {
+        let chain = self.chain(first)?;
+        let length = chain.len() * BLOCK_SIZE;
+        let mut frame = [0u8; FRAME_SIZE];
+
+        if buffer.len() < length {
+            return Err(Error::NoSpace);
+        }
+
+        for (block, data) in chain.as_slice().iter().zip(buffer.chunks_mut(BLOCK_SIZE)) {
+            for (x, chunk) in data.chunks_mut(FRAME_SIZE).enumerate() {
+                self.read(u16::from(*block) * FRAMES_PER_BLOCK + x as u16, &mut frame)?;
+                chunk.copy_from_slice(&frame);
+            }
+        }
+
+        Ok(length)
+    }
+
+    /// Write out directory entries that changed since `before`
+    pub(crate) fn commit(
+        &mut self,
+        before: &[DirectoryEntry; SAVE_BLOCKS],
+    ) -> Result<(), Error<S::Error>> {
+        for (x, before) in before.iter().enumerate() {
+            let entry = self.entries[x].data;
+
+            if entry[..] != before.data[..] {
+                self.write(DIRECTORY_FRAME + x as u16, &entry)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Claim enough free blocks for `size` bytes, only in memory
+    pub(crate) fn reserve(
+        &mut self,
+        filename: &[u8],
+        size: usize,
+    ) -> Result<Chain, Error<S::Error>> {
+        let blocks = core::cmp::max(1, size.div_ceil(BLOCK_SIZE));
+        let mut chain = Chain::new();
+
+        if filename.len() > FILENAME_LENGTH {
+            return Err(Error::BadLength);
+        }
+
+        if blocks > self.free_blocks() {
+            return Err(Error::NoSpace);
+        }
+
+        // Prefer blocks that were never used so deleted saves can still be brought back
+        for deleted in [false, true].iter() {
+            for (x, entry) in self.entries.iter().enumerate() {
+                let state = entry.state();
+                let wanted = if *deleted {
+                    state.deleted()
+                } else {
+                    state == BlockState::Free
+                };
+
+                if wanted && chain.len() < blocks {
+                    chain.push(x as u8 + 1);
+                }
+            }
+        }
+
+        for (x, block) in chain.as_slice().iter().enumerate() {
+            let state = match x {
+                0 => BlockState::First,
+                x if x == blocks - 1 => BlockState::Last,
+                _ => BlockState::Middle,
+            };
+
+            let mut entry = DirectoryEntry::free();
+            entry.set_state(state);
+            entry.set_next(chain.as_slice().get(x + 1).cloned());
+
+            if x == 0 {
+                entry.set_size((blocks * BLOCK_SIZE) as u32);
+                entry.set_filename(filename);
+            }
+
+            self.entries[usize::from(*block) - 1] = entry;
+        }
+
+        Ok(chain)
+    }
+
+    /// Claim blocks for a save of `size` bytes without writing anything to them.
+    /// Returns the blocks in the order the save should be written.
+    pub fn allocate(&mut self, filename: &[u8], size: usize) -> Result<Chain, Error<S::Error>> {
+        let before = self.entries;
+        let chain = self.reserve(filename, size)?;
+
+        if let Err(x) = self.commit(&before) {
+            self.entries = before;
+            return Err(x);
+        }
+
+        Ok(chain)
+    }
+
+    /// Give back every block of a save. The data is left alone but the blocks
+    /// are up for grabs.
+    pub fn free(&mut self, first: u8) -> Result<(), Error<S::Error>> {
+        let before = self.entries;
+        let chain = self.chain(first)?;
+
+        for block in chain.as_slice() {
+            self.entries[usize::from(*block) - 1] = DirectoryEntry::free();
+        }
+
+        if let Err(x) = self.commit(&before) {
+            self.entries = before;
+            return Err(x);
+        }
+
+        Ok(())
+    }
+
+    /// Write a new save, returning the block it starts at. The data is padded
+    /// out to a whole number of blocks. The directory is only updated once all
+    /// the data is written.
+    pub fn write_save(&mut self, filename: &[u8], data: &[u8]) -> Result<u8, Error<S::Error>> {
+        let before = self.entries;
+        let chain = self.reserve(filename, data.len())?;
+
+        let result = self
+            .write_chain(&chain, data)
+            .and_then(|_| self.commit(&before));
+        if let Err(x) = result {
+            self.entries = before;
+            return Err(x);
+        }
+
+        Ok(chain.as_slice()[0])
+    }
+
+    fn write_chain(&mut self, chain: &Chain, data: &[u8]) -> Result<(), Error<S::Error>> {
+        for (x, block) in chain.as_slice().iter().enumerate() {
+            for y in 0..FRAMES_PER_BLOCK {
+                let offset = x * BLOCK_SIZE + usize::from(y) * FRAME_SIZE;
+                let mut frame = [0u8; FRAME_SIZE];
+
+                if let Some(chunk) = data.get(offset..) {
+                    let length = core::cmp::min(chunk.len(), FRAME_SIZE);
+                    frame[..length].copy_from_slice(&chunk[..length]);
+                }
+
+                self.write(u16::from(*block) * FRAMES_PER_BLOCK + y, &frame)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{FrameStore, CARD_SIZE, FRAME_SIZE};
+    use super::{BlockState, Filesystem, BLOCK_SIZE};
+    use crate::Error;
+
+    #[test]
+    fn save_chains() {
+        let mut card = [0u8; CARD_SIZE];
+        let mut fs = Filesystem::format(&mut card).unwrap();
+        let mut buffer = [0u8; 3 * BLOCK_SIZE];
+
+        let first = fs.write_save(b"BESLES-00000ONE", &[0x11; 100]).unwrap();
+        let second = fs
+            .write_save(b"BASCUS-94228TWO", &[0x22; 2 * BLOCK_SIZE + 1])
+            .unwrap();
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(fs.chain(second).unwrap().as_slice(), &[2, 3, 4]);
+        assert_eq!(fs.entry(4).unwrap().state(), BlockState::Last);
+        assert_eq!(fs.free_blocks(), 11);
+
+        fs.free(first).unwrap();
+        assert!(matches!(
+            fs.read_save(first, &mut buffer),
+            Err(Error::NotFound)
+        ));
+
+        // Freed blocks get reused first and the directory made it to the card
+        let mut fs = Filesystem::open(fs.into_inner()).unwrap();
+        assert_eq!(fs.find(b"BASCUS-94228TWO"), Some(2));
+        assert_eq!(fs.entry(2).unwrap().product_code(), b"SCUS-94228");
+        assert!(fs.entry(2).unwrap().checksum_valid());
+        assert_eq!(fs.read_save(2, &mut buffer).unwrap(), 3 * BLOCK_SIZE);
+        assert_eq!(buffer[2 * BLOCK_SIZE], 0x22);
+        assert_eq!(buffer[2 * BLOCK_SIZE + 1], 0x00);
+
+        assert!(matches!(
+            fs.write_save(b"TOO-BIG", &[0u8; 13 * BLOCK_SIZE]),
+            Err(Error::NoSpace)
+        ));
+        assert_eq!(fs.saves().count(), 1);
+    }
+
+    #[test]
+    fn broken_frames() {
+        let mut card = [0u8; CARD_SIZE];
+        let mut frame = [0xffu8; FRAME_SIZE];
+
+        Filesystem::format(&mut card).unwrap();
+
+        // The first frame of block 1 went bad and was moved to the second replacement
+        frame[..4].copy_from_slice(&[64, 0, 0, 0]);
+        card.write_frame(17, &frame).unwrap();
+
+        let mut fs = Filesystem::open(&mut card).unwrap();
+        fs.write_save(b"BISLPS-00000", &[0x33; 10]).unwrap();
+
+        card.read_frame(37, &mut frame).unwrap();
+        assert_eq!(frame[9], 0x33);
+        card.read_frame(64, &mut frame).unwrap();
+        assert_eq!(frame[9], 0x00);
+    }
+
+    #[test]
+    fn corrupt_chains() {
+        let mut card = [0u8; CARD_SIZE];
+        let mut fs = Filesystem::format(&mut card).unwrap();
+
+        fs.write_save(b"LOOP", &[0u8; 2 * BLOCK_SIZE]).unwrap();
+        fs.entries[1].set_next(Some(1));
+        assert!(matches!(fs.chain(1), Err(Error::Corrupt)));
+        assert!(matches!(fs.chain(2), Err(Error::NotFound)));
+
+        card[0] = 0;
+        assert!(matches!(Filesystem::open(&mut card), Err(Error::Corrupt)));
+    }
+}
@@ -0,0 +1,231 @@
+//! Save Titles and Icons
+//! ============================
+//! The first frame of every save says what it is. It starts with "SC", then how
+//! many frames of icon animation there are, then a title in Shift-JIS and a
+//! palette of sixteen 15-bit colours. The next one to three frames are the icon
+//! itself, 16x16 pixels at four bits each.
+//!
+//! Titles come out as raw Shift-JIS unless the `std` feature is on, in which case
+//! `SaveHeader::title` will turn them into a `String`.
+
+use super::filesystem::{Filesystem, FRAMES_PER_BLOCK};
+use super::{Frame, FrameStore, FRAME_SIZE};
+use crate::Error;
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Frames read to get the title and every icon frame
+pub const HEADER_LENGTH: usize = FRAME_SIZE * 4;
+/// Pixels along each side of an icon
+pub const ICON_SIZE: usize = 16;
+/// Bytes of an icon frame turned into RGBA
+pub const ICON_RGBA_LENGTH: usize = ICON_SIZE * ICON_SIZE * 4;
+
+const HEADER_MAGIC: &[u8] = b"SC";
+const TITLE_OFFSET: usize = 0x04;
+const TITLE_LENGTH: usize = 64;
+const PALETTE_OFFSET: usize = 0x60;
+const PALETTE_LENGTH: usize = 16;
+
+/// The title frame and icon frames at the start of a save
+#[derive(Clone, Copy)]
+pub struct SaveHeader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> SaveHeader<'a> {
+    /// Make sense of the start of a save. Returns `None` if it isn't long enough
+    /// for the icon frames it claims to have, or doesn't start with "SC".
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        if data.len() < FRAME_SIZE || !data.starts_with(HEADER_MAGIC) {
+            return None;
+        }
+
+        let header = Self { data };
+
+        if data.len() < FRAME_SIZE * (1 + usize::from(header.icon_frames())) {
+            return None;
+        }
+
+        Some(header)
+    }
+
+    /// How many frames of icon animation there are, from 1 to 3. Anything the
+    /// save has that isn't one of those counts as none.
+    pub fn icon_frames(&self) -> u8 {
+        match self.data[2] {
+            x @ 0x11..=0x13 => x - 0x10,
+            _ => 0,
+        }
+    }
+
+    /// How many blocks the save says it takes up
+    pub fn blocks(&self) -> u8 {
+        self.data[3]
+    }
+
+    /// The title as stored, in Shift-JIS, without the trailing zeros
+    pub fn title_bytes(&self) -> &'a [u8] {
+        let title = &self.data[TITLE_OFFSET..TITLE_OFFSET + TITLE_LENGTH];
+        let length = title.iter().position(|x| *x == 0).unwrap_or(title.len());
+
+        &title[..length]
+    }
+
+    /// The title, decoded. Games love full-width characters, so don't be
+    /// surprised by them.
+    #[cfg(feature = "std")]
+    pub fn title(&self) -> String {
+        let (title, _) = encoding_rs::SHIFT_JIS.decode_without_bom_handling(self.title_bytes());
+
+        title.into_owned()
+    }
+
+    /// The sixteen colours of the icon as stored. Red is in the lowest five
+    /// bits, then green, then blue, with the top bit for semi-transparency.
+    pub fn palette(&self) -> [u16; PALETTE_LENGTH] {
+        let mut palette = [0u16; PALETTE_LENGTH];
+
+        LittleEndian::read_u16_into(
+            &self.data[PALETTE_OFFSET..PALETTE_OFFSET + PALETTE_LENGTH * 2],
+            &mut palette,
+        );
+
+        palette
+    }
+
+    /// The palette as RGBA. Colour zero is see-through, like it is on the console.
+    pub fn palette_rgba(&self) -> [[u8; 4]; PALETTE_LENGTH] {
+        let mut rgba = [[0u8; 4]; PALETTE_LENGTH];
+
+        for (colour, rgba) in self.palette().iter().zip(rgba.iter_mut()) {
+            // Spread five bits across all eight so white stays white
+            let expand = |x: u16| {
+                let x = (x & 0x1f) as u8;
+                (x << 3) | (x >> 2)
+            };
+
+            *rgba = [
+                expand(*colour),
+                expand(*colour >> 5),
+                expand(*colour >> 10),
+                if *colour == 0 { 0 } else { 0xff },
+            ];
+        }
+
+        rgba
+    }
+
+    /// The raw pixels of an icon frame, two to a byte with the left one in the
+    /// low nybble
+    pub fn icon_pixels(&self, frame: u8) -> Option<&'a [u8]> {
+        if frame >= self.icon_frames() {
+            return None;
+        }
+
+        let offset = FRAME_SIZE * (1 + usize::from(frame));
+
+        Some(&self.data[offset..offset + FRAME_SIZE])
+    }
+
+    /// The palette index of a single pixel of an icon frame
+    pub fn icon_pixel(&self, frame: u8, x: usize, y: usize) -> Option<u8> {
+        let pixels = self.icon_pixels(frame)?;
+
+        if x >= ICON_SIZE || y >= ICON_SIZE {
+            return None;
+        }
+
+        let pair = pixels[(y * ICON_SIZE + x) / 2];
+
+        Some((pair >> (4 * (x & 1))) & 0x0f)
+    }
+
+    /// An icon frame as RGBA, a row at a time from the top left
+    pub fn icon_rgba(&self, frame: u8) -> Option<[u8; ICON_RGBA_LENGTH]> {
+        let pixels = self.icon_pixels(frame)?;
+        let palette = self.palette_rgba();
+        let mut rgba = [0u8; ICON_RGBA_LENGTH];
+
+        for (pair, rgba) in pixels.iter().zip(rgba.chunks_mut(8)) {
+            rgba[..4].copy_from_slice(&palette[usize::from(pair & 0x0f)]);
+            rgba[4..].copy_from_slice(&palette[usize::from(pair >> 4)]);
+        }
+
+        Some(rgba)
+    }
+}
+
+impl<S: FrameStore> Filesystem<S> {
+    /// Read just enough of a save for `SaveHeader` to make sense of it
+    pub fn read_header(
+        &mut self,
+        first: u8,
+        buffer: &mut [u8; HEADER_LENGTH],
+    ) -> Result<(), Error<S::Error>> {
+        self.chain(first)?;
+
+        let mut frame: Frame = [0u8; FRAME_SIZE];
+        for (x, chunk) in buffer.chunks_mut(FRAME_SIZE).enumerate() {
+            self.read(u16::from(first) * FRAMES_PER_BLOCK + x as u16, &mut frame)?;
+            chunk.copy_from_slice(&frame);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SaveHeader, HEADER_LENGTH};
+
+    fn header() -> [u8; HEADER_LENGTH] {
+        let mut data = [0u8; HEADER_LENGTH];
+
+        data[..4].copy_from_slice(b"SC\x12\x01");
+        // "ＡＢ" in full-width, then "ｱ" in half-width kana
+        data[4..9].copy_from_slice(&[0x82, 0x60, 0x82, 0x61, 0xb1]);
+        // Colour one is white, colour two is pure blue
+        data[0x62..0x66].copy_from_slice(&[0xff, 0x7f, 0x00, 0x7c]);
+        data[128] = 0x21;
+        data[256 + 127] = 0x10;
+
+        data
+    }
+
+    #[test]
+    fn save_header() {
+        let data = header();
+        let header = SaveHeader::new(&data).unwrap();
+
+        assert_eq!(header.icon_frames(), 2);
+        assert_eq!(header.blocks(), 1);
+        assert_eq!(header.title_bytes(), &[0x82, 0x60, 0x82, 0x61, 0xb1]);
+        assert_eq!(header.palette()[1], 0x7fff);
+
+        let palette = header.palette_rgba();
+        assert_eq!(palette[0], [0, 0, 0, 0]);
+        assert_eq!(palette[1], [0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(palette[2], [0, 0, 0xff, 0xff]);
+
+        assert_eq!(header.icon_pixel(0, 0, 0), Some(1));
+        assert_eq!(header.icon_pixel(0, 1, 0), Some(2));
+        assert_eq!(header.icon_pixel(1, 15, 15), Some(1));
+        assert_eq!(header.icon_pixel(2, 0, 0), None);
+
+        let rgba = header.icon_rgba(0).unwrap();
+        assert_eq!(&rgba[..8], &[0xff, 0xff, 0xff, 0xff, 0, 0, 0xff, 0xff]);
+        assert_eq!(&rgba[8..12], &[0, 0, 0, 0]);
+
+        assert!(SaveHeader::new(&data[..200]).is_none());
+        assert!(SaveHeader::new(&[0u8; HEADER_LENGTH]).is_none());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn save_title() {
+        let data = header();
+        let header = SaveHeader::new(&data).unwrap();
+
+        assert_eq!(header.title(), "ＡＢｱ");
+    }
+}
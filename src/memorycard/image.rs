@@ -0,0 +1,424 @@
+//! Memory Card Images
+//! ============================
+//! Emulators and save managers keep cards and saves in files. All of them are the
+//! same frames with a different header in front, so an `Image` only has to know
+//! where the card starts.
+//!
+//! Whole cards:
+//!
+//! * Raw (`.mcr`, `.mcd`, `.srm` and friends) is the card and nothing else
+//! * DexDrive (`.gme`) has a 3904 byte header with a comment for every block
+//! * PSP and Vita (`.vmp`) have a 128 byte header with a signature
+//!
+//! Single saves, see `Filesystem::import_save` and `Filesystem::export_save`:
+//!
+//! * `.mcs` has the save's first directory frame in front of it
+//! * Action Replay (`.psx`, `.mcb`) has the filename and a title in front of it
+//!
+//! Everything works on byte slices, so reading and writing the files themselves
+//! is up to you.
+//!
+//! ```
+//! use pscontroller_rs::memorycard::image::{Format, Image};
+//! use pscontroller_rs::memorycard::CARD_SIZE;
+//!
+//! let mut file = [0u8; CARD_SIZE];
+//! let image = Image::open(&mut file).unwrap();
+//! assert_eq!(image.format(), Format::Raw);
+//!
+//! // Turn it into something a DexDrive would understand
+//! let mut gme = [0u8; 3904 + CARD_SIZE];
+//! let length = image.export(Format::DexDrive, &mut gme).unwrap();
+//! assert_eq!(&gme[..11], b"123-456-STD");
+//! assert_eq!(length, gme.len());
+//! ```
+
+use super::filesystem::{Filesystem, BLOCK_SIZE, FILENAME_LENGTH, SAVE_BLOCKS};
+use super::{Frame, FrameStore, Widen, CARD_SIZE, FRAME_COUNT, FRAME_SIZE};
+use crate::Error;
+use core::convert::Infallible;
+
+const DEXDRIVE_MAGIC: &[u8] = b"123-456-STD";
+const DEXDRIVE_HEADER: usize = 3904;
+const DEXDRIVE_STATES: usize = 22;
+const DEXDRIVE_LINKS: usize = 38;
+const DEXDRIVE_COMMENTS: usize = 64;
+const DEXDRIVE_COMMENT_LENGTH: usize = 256;
+
+const VMP_MAGIC: &[u8] = b"\0PMV";
+const VMP_HEADER: usize = 0x80;
+
+const MCS_HEADER: usize = FRAME_SIZE;
+const ACTION_REPLAY_HEADER: usize = 54;
+const ACTION_REPLAY_TITLE: usize = 21;
+
+/// Ways of storing a whole card in a file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Just the card
+    Raw,
+    /// DexDrive, with a comment for each block
+    DexDrive,
+    /// PSP and Vita virtual memory card
+    Vmp,
+}
+
+impl Format {
+    /// Bytes in front of the card
+    pub fn header_length(self) -> usize {
+        match self {
+            Format::Raw => 0,
+            Format::DexDrive => DEXDRIVE_HEADER,
+            Format::Vmp => VMP_HEADER,
+        }
+    }
+
+    /// Bytes in a file of this format
+    pub fn file_length(self) -> usize {
+        self.header_length() + CARD_SIZE
+    }
+
+    /// Work out the format from a file's contents
+    fn detect(file: &[u8]) -> Option<Self> {
+        [Format::DexDrive, Format::Vmp, Format::Raw]
+            .iter()
+            .cloned()
+            .find(|x| file.len() == x.file_length() && file.starts_with(x.magic()))
+    }
+
+    fn magic(self) -> &'static [u8] {
+        match self {
+            Format::Raw => &[],
+            Format::DexDrive => DEXDRIVE_MAGIC,
+            Format::Vmp => VMP_MAGIC,
+        }
+    }
+}
+
+/// Ways of storing a single save in a file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SaveFormat {
+    /// `.mcs`, the directory frame followed by the save
+    Mcs,
+    /// Action Replay `.psx` or `.mcb`, the filename and title followed by the save
+    ActionReplay,
+}
+
+impl SaveFormat {
+    /// Bytes in front of the save
+    pub fn header_length(self) -> usize {
+        match self {
+            SaveFormat::Mcs => MCS_HEADER,
+            SaveFormat::ActionReplay => ACTION_REPLAY_HEADER,
+        }
+    }
+
+    /// Work out the format from a file's length. The headers are different
+    /// enough that there's no mixing them up.
+    fn detect(file: &[u8]) -> Option<Self> {
+        [SaveFormat::Mcs, SaveFormat::ActionReplay]
+            .iter()
+            .cloned()
+            .find(|x| {
+                let blocks = file.len().wrapping_sub(x.header_length()) / BLOCK_SIZE;
+
+                file.len() == x.header_length() + blocks * BLOCK_SIZE
+                    && (1..=SAVE_BLOCKS).contains(&blocks)
+            })
+    }
+}
+
+/// A card image held in memory, the file's header and all
+pub struct Image<'a> {
+    file: &'a mut [u8],
+    format: Format,
+}
+
+impl<'a> Image<'a> {
+    /// Use the contents of a card image file, working out which format it's in
+    pub fn open(file: &'a mut [u8]) -> Result<Self, Error<Infallible>> {
+        let format = Format::detect(file).ok_or(Error::UnknownFormat)?;
+
+        Ok(Self { file, format })
+    }
+
+    /// Make a new image in `buffer` with a header for `format` and a card full
+    /// of zeros. Use `Filesystem::format` to make the card usable.
+    pub fn create(format: Format, buffer: &'a mut [u8]) -> Result<Self, Error<Infallible>> {
+        let file = buffer
+            .get_mut(..format.file_length())
+            .ok_or(Error::NoSpace)?;
+
+        for x in file.iter_mut() {
+            *x = 0;
+        }
+
+        file[..format.magic().len()].copy_from_slice(format.magic());
+
+        match format {
+            Format::Raw => {}
+            // These match what other DexDrive tools put there, whatever they mean
+            Format::DexDrive => {
+                file[18] = 0x01;
+                file[20] = 0x01;
+                file[21] = b'M';
+            }
+            // The PSP checks a signature made with keys we don't have. Emulators
+            // don't care, but a real PSP will want the image signed again.
+            Format::Vmp => file[4] = VMP_HEADER as u8,
+        }
+
+        let mut image = Self { file, format };
+        image.sync_header();
+
+        Ok(image)
+    }
+
+    /// What kind of file the image is
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// The card, without the header
+    pub fn card(&self) -> &[u8] {
+        &self.file[self.format.header_length()..]
+    }
+
+    /// The whole file
+    pub fn as_bytes(&self) -> &[u8] {
+        self.file
+    }
+
+    /// The DexDrive's comment for a block, from 1 to 15. Other formats don't
+    /// have any.
+    pub fn comment(&self, block: u8) -> Option<&[u8]> {
+        if self.format != Format::DexDrive || !(1..=SAVE_BLOCKS as u8).contains(&block) {
+            return None;
+        }
+
+        let offset = DEXDRIVE_COMMENTS + usize::from(block - 1) * DEXDRIVE_COMMENT_LENGTH;
+        let comment = &self.file[offset..offset + DEXDRIVE_COMMENT_LENGTH];
+        let length = comment
+            .iter()
+            .position(|x| *x == 0)
+            .unwrap_or(comment.len());
+
+        Some(&comment[..length])
+    }
+
+    /// Write the card out in another format, returning how many bytes that took
+    pub fn export(&self, format: Format, buffer: &mut [u8]) -> Result<usize, Error<Infallible>> {
+        let mut image = Image::create(format, buffer)?;
+
+        let offset = format.header_length();
+        image.file[offset..].copy_from_slice(self.card());
+        image.sync_header();
+
+        Ok(format.file_length())
+    }
+
+    /// The DexDrive keeps its own copy of part of the directory
+    fn sync_header(&mut self) {
+        if self.format != Format::DexDrive {
+            return;
+        }
+
+        for x in 0..SAVE_BLOCKS {
+            let entry = DEXDRIVE_HEADER + (x + 1) * FRAME_SIZE;
+
+            self.file[DEXDRIVE_STATES + x] = self.file[entry];
+            self.file[DEXDRIVE_LINKS + x] = self.file[entry + 8];
+        }
+    }
+}
+
+impl<'a> FrameStore for Image<'a> {
+    type Error = Infallible;
+
+    fn read_frame(&mut self, frame: u16, data: &mut Frame) -> Result<(), Error<Infallible>> {
+        let offset = self.format.header_length() + usize::from(frame) * FRAME_SIZE;
+
+        if frame >= FRAME_COUNT {
+            return Err(Error::BadSector);
+        }
+
+        data.copy_from_slice(&self.file[offset..offset + FRAME_SIZE]);
+
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: u16, data: &Frame) -> Result<(), Error<Infallible>> {
+        let offset = self.format.header_length() + usize::from(frame) * FRAME_SIZE;
+
+        if frame >= FRAME_COUNT {
+            return Err(Error::BadSector);
+        }
+
+        self.file[offset..offset + FRAME_SIZE].copy_from_slice(data);
+
+        if (1..=SAVE_BLOCKS as u16).contains(&frame) {
+            self.sync_header();
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, E> Widen<E> for Image<'a> {
+    fn widen(error: Error<Infallible>) -> Error<E> {
+        error.widen()
+    }
+}
+
+impl<S: FrameStore> Filesystem<S> {
+    /// Write a save out as a single save file, returning how many bytes that took
+    pub fn export_save(
+        &mut self,
+        first: u8,
+        format: SaveFormat,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error<S::Error>> {
+        let chain = self.chain(first)?;
+        let header = format.header_length();
+        let length = header + chain.len() * BLOCK_SIZE;
+
+        let file = buffer.get_mut(..length).ok_or(Error::NoSpace)?;
+        self.read_save(first, &mut file[header..])?;
+
+        let mut entry = *self.entry(first).ok_or(Error::NotFound)?;
+
+        for x in file[..header].iter_mut() {
+            *x = 0;
+        }
+
+        match format {
+            SaveFormat::Mcs => {
+                // Where the save went next on this card means nothing on another
+                entry.set_next(None);
+                file[..header].copy_from_slice(entry.as_bytes());
+            }
+            SaveFormat::ActionReplay => {
+                let filename = entry.filename();
+                file[..filename.len()].copy_from_slice(filename);
+
+                // The title is Shift-JIS but Action Replay wants ASCII, so only keep
+                // it if it already is
+                let mut title = [0u8; ACTION_REPLAY_HEADER - ACTION_REPLAY_TITLE - 1];
+                let source = &file[header + 4..header + 4 + title.len()];
+                let length = source.iter().position(|x| *x == 0).unwrap_or(source.len());
+
+                if source[..length].iter().all(|x| (0x20..0x7f).contains(x)) {
+                    title[..length].copy_from_slice(&source[..length]);
+                }
+
+                file[ACTION_REPLAY_TITLE..ACTION_REPLAY_TITLE + title.len()]
+                    .copy_from_slice(&title);
+            }
+        }
+
+        Ok(length)
+    }
+
+    /// Write a single save file to the card, working out its format from its
+    /// length. Returns the block the save starts at.
+    pub fn import_save(&mut self, file: &[u8]) -> Result<u8, Error<S::Error>> {
+        let format = SaveFormat::detect(file).ok_or(Error::UnknownFormat)?;
+        let header = &file[..format.header_length()];
+
+        let filename = match format {
+            SaveFormat::Mcs => &header[0x0a..0x0a + FILENAME_LENGTH],
+            SaveFormat::ActionReplay => &header[..FILENAME_LENGTH],
+        };
+        let filename = &filename[..filename
+            .iter()
+            .position(|x| *x == 0)
+            .unwrap_or(filename.len())];
+
+        self.write_save(filename, &file[format.header_length()..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::filesystem::{Filesystem, BLOCK_SIZE};
+    use super::super::CARD_SIZE;
+    use super::{Format, Image, SaveFormat};
+    use crate::Error;
+
+    #[test]
+    fn card_formats() {
+        let mut card = [0u8; CARD_SIZE];
+        Filesystem::format(&mut card).unwrap();
+        let mut gme = [0u8; 3904 + CARD_SIZE];
+        let mut vmp = [0u8; 0x80 + CARD_SIZE];
+
+        let mut image = Image::open(&mut card).unwrap();
+        Filesystem::open(&mut image)
+            .unwrap()
+            .write_save(b"BASLUS-00001", &[0x42; 10])
+            .unwrap();
+        image.export(Format::DexDrive, &mut gme).unwrap();
+
+        let image = Image::open(&mut gme).unwrap();
+        assert_eq!(image.format(), Format::DexDrive);
+        assert_eq!(image.as_bytes()[22], 0x51);
+        assert_eq!(image.as_bytes()[23], 0xa0);
+        assert_eq!(image.comment(1), Some(&[][..]));
+        image.export(Format::Vmp, &mut vmp).unwrap();
+
+        let mut image = Image::open(&mut vmp).unwrap();
+        assert_eq!(image.format(), Format::Vmp);
+        let fs = Filesystem::open(&mut image).unwrap();
+        assert_eq!(fs.find(b"BASLUS-00001"), Some(1));
+
+        assert!(matches!(
+            Image::open(&mut [0u8; 1000]),
+            Err(Error::UnknownFormat)
+        ));
+        assert!(matches!(
+            Image::create(Format::Vmp, &mut [0u8; CARD_SIZE]),
+            Err(Error::NoSpace)
+        ));
+    }
+
+    #[test]
+    fn save_formats() {
+        let mut card = [0u8; CARD_SIZE];
+        let mut other = [0u8; CARD_SIZE];
+        Filesystem::format(&mut other).unwrap();
+        let mut file = [0u8; 128 + 2 * BLOCK_SIZE];
+        let mut save = [0x24u8; BLOCK_SIZE + 1];
+        save[..4].copy_from_slice(b"SC\x11\x01");
+        save[4..9].copy_from_slice(b"TITLE");
+        save[9] = 0;
+
+        let mut fs = Filesystem::format(&mut card).unwrap();
+        let block = fs.write_save(b"BESLES-12345SAVE", &save).unwrap();
+        let length = fs.export_save(block, SaveFormat::Mcs, &mut file).unwrap();
+        assert_eq!(length, file.len());
+        assert_eq!(file[0], 0x51);
+        assert_eq!(&file[0x0a..0x1a], b"BESLES-12345SAVE");
+
+        let mut other_fs = Filesystem::open(&mut other).unwrap();
+        let block = other_fs.import_save(&file).unwrap();
+        assert_eq!(other_fs.find(b"BESLES-12345SAVE"), Some(block));
+
+        let length = fs
+            .export_save(block, SaveFormat::ActionReplay, &mut file)
+            .unwrap();
+        assert_eq!(length, 54 + 2 * BLOCK_SIZE);
+        assert_eq!(&file[..16], b"BESLES-12345SAVE");
+        assert_eq!(&file[21..27], b"TITLE\0");
+
+        other_fs.free(block).unwrap();
+        other_fs.import_save(&file[..length]).unwrap();
+        let mut buffer = [0u8; 2 * BLOCK_SIZE];
+        other_fs.read_save(block, &mut buffer).unwrap();
+        assert_eq!(buffer[..save.len()], save[..]);
+
+        assert!(matches!(
+            other_fs.import_save(&file[..100]),
+            Err(Error::UnknownFormat)
+        ));
+    }
+}
@@ -0,0 +1,447 @@
+//! Housekeeping for a card: formatting, deleting and bringing back saves,
+//! copying them between cards and squeezing out the gaps between them.
+//!
+//! Anything that changes the directory writes the save's data first and the
+//! directory frames last. If the bus drops out halfway, the worst that happens
+//! is a save shows up twice or not at all, never a save pointing at half-written
+//! blocks.
+
+use super::filesystem::{
+    checksum, BlockState, DirectoryEntry, Filesystem, BLOCK_SIZE, BROKEN_COUNT, BROKEN_LIST_FRAME,
+    DIRECTORY_FRAME, FRAMES_PER_BLOCK, HEADER_COPY_FRAME, HEADER_MAGIC, NO_BROKEN_FRAME,
+    SAVE_BLOCKS,
+};
+use super::image::SaveFormat;
+use super::{Frame, FrameStore, Widen, FRAME_SIZE};
+use crate::{Error, MultitapPort, PlayStationPort};
+use byteorder::{ByteOrder, LittleEndian};
+use hal::digital::OutputPin;
+use hal::spi::SpiBus;
+
+impl<S: FrameStore> Filesystem<S> {
+    /// Wipe the card's directory and make it usable. The data in each block is
+    /// left alone, but nothing points to it anymore.
+    pub fn format(mut store: S) -> Result<Self, Error<S::Error>> {
+        let mut frame: Frame = [0u8; FRAME_SIZE];
+
+        // Nothing has gone bad yet
+        LittleEndian::write_u32(&mut frame[0..4], NO_BROKEN_FRAME);
+        LittleEndian::write_u16(&mut frame[8..10], 0xffff);
+        frame[FRAME_SIZE - 1] = checksum(&frame);
+        for x in 0..BROKEN_COUNT as u16 {
+            store.write_frame(BROKEN_LIST_FRAME + x, &frame)?;
+        }
+
+        let entries = [DirectoryEntry::free(); SAVE_BLOCKS];
+        for (x, entry) in entries.iter().enumerate() {
+            store.write_frame(DIRECTORY_FRAME + x as u16, entry.as_bytes())?;
+        }
+
+        // The header goes last so a card that didn't finish formatting won't open
+        let mut frame: Frame = [0u8; FRAME_SIZE];
+        frame[..HEADER_MAGIC.len()].copy_from_slice(HEADER_MAGIC);
+        frame[FRAME_SIZE - 1] = checksum(&frame);
+        store.write_frame(HEADER_COPY_FRAME, &frame)?;
+        store.write_frame(0, &frame)?;
+
+        Ok(Self {
+            store,
+            entries,
+            broken: [NO_BROKEN_FRAME; BROKEN_COUNT],
+        })
+    }
+
+    /// Change the state of every block of a save
+    fn restate(
+        &mut self,
+        first: u8,
+        states: [BlockState; 3],
+        deleted: bool,
+    ) -> Result<(), Error<S::Error>> {
+        let before = self.entries;
+        let chain = self.chain(first)?;
+
+        if self.entries[usize::from(first) - 1].state().deleted() != deleted {
+            return Err(Error::NotFound);
+        }
+
+        for (x, block) in chain.as_slice().iter().enumerate() {
+            let state = match x {
+                0 => states[0],
+                x if x == chain.len() - 1 => states[2],
+                _ => states[1],
+            };
+
+            self.entries[usize::from(*block) - 1].set_state(state);
+        }
+
+        if let Err(x) = self.commit(&before) {
+            self.entries = before;
+            return Err(x);
+        }
+
+        Ok(())
+    }
+
+    /// Delete a save. Its blocks are up for grabs, but until they're reused the
+    /// save can be brought back with `undelete`.
+    pub fn delete(&mut self, first: u8) -> Result<(), Error<S::Error>> {
+        let states = [
+            BlockState::DeletedFirst,
+            BlockState::DeletedMiddle,
+            BlockState::DeletedLast,
+        ];
+
+        self.restate(first, states, false)
+    }
+
+    /// Bring back a deleted save, as long as none of its blocks have been used
+    /// for something else since
+    pub fn undelete(&mut self, first: u8) -> Result<(), Error<S::Error>> {
+        let states = [BlockState::First, BlockState::Middle, BlockState::Last];
+
+        self.restate(first, states, true)
+    }
+
+    /// Copy a save from another card, or an image of one, returning the block it
+    /// starts at here
+    pub fn copy_save<T>(
+        &mut self,
+        source: &mut Filesystem<T>,
+        first: u8,
+    ) -> Result<u8, Error<S::Error>>
+    where
+        S: Widen<<S as FrameStore>::Error>,
+        T: Widen<<S as FrameStore>::Error>,
+    {
+        copy(source, self, first)
+    }
+
+    /// Copy a save from here onto another card, or an image of one, returning the
+    /// block it starts at there
+    pub fn copy_save_to<T>(
+        &mut self,
+        target: &mut Filesystem<T>,
+        first: u8,
+    ) -> Result<u8, Error<S::Error>>
+    where
+        S: Widen<<S as FrameStore>::Error>,
+        T: Widen<<S as FrameStore>::Error>,
+    {
+        copy(self, target, first)
+    }
+
+    /// Write a single directory entry out to the card
+    fn write_entry(&mut self, block: u8) -> Result<(), Error<S::Error>> {
+        let entry = self.entries[usize::from(block) - 1];
+
+        self.write(DIRECTORY_FRAME + u16::from(block) - 1, entry.as_bytes())
+    }
+
+    /// Move a block of a save somewhere else. The new entry is written first, then
+    /// whatever points at it, and the old one is only freed after that.
+    fn relocate(&mut self, from: u8, to: u8) -> Result<(), Error<S::Error>> {
+        let mut frame: Frame = [0u8; FRAME_SIZE];
+
+        for x in 0..FRAMES_PER_BLOCK {
+            self.read(u16::from(from) * FRAMES_PER_BLOCK + x, &mut frame)?;
+            self.write(u16::from(to) * FRAMES_PER_BLOCK + x, &frame)?;
+        }
+
+        let before = self.entries;
+        let previous = (1..=SAVE_BLOCKS as u8).find(|x| {
+            let entry = &self.entries[usize::from(*x) - 1];
+            entry.state().in_use() && entry.next() == Some(from)
+        });
+
+        self.entries[usize::from(to) - 1] = self.entries[usize::from(from) - 1];
+        self.entries[usize::from(from) - 1] = DirectoryEntry::free();
+        if let Some(x) = previous {
+            self.entries[usize::from(x) - 1].set_next(Some(to));
+        }
+
+        let mut result = self.write_entry(to);
+        if let Some(x) = previous {
+            result = result.and_then(|_| self.write_entry(x));
+        }
+        result = result.and_then(|_| self.write_entry(from));
+
+        if result.is_err() {
+            self.entries = before;
+        }
+
+        result
+    }
+
+    /// Move every save to the front of the card, each in one unbroken run of
+    /// blocks in the order they already appear. Deleted saves are written over
+    /// and can't be brought back afterwards.
+    ///
+    /// Blocks are moved one at a time, so a save has to be shuffled through a
+    /// free block to get out of the way. A full card that's out of order will
+    /// fail with `NoSpace`.
+    pub fn defragment(&mut self) -> Result<(), Error<S::Error>> {
+        let mut firsts = [0u8; SAVE_BLOCKS];
+        let mut saves = 0;
+        for save in self.saves() {
+            firsts[saves] = save.block();
+            saves += 1;
+        }
+
+        let mut target = 1u8;
+
+        for save in 0..saves {
+            let mut index = 0;
+
+            while index < self.chain(firsts[save])?.len() {
+                // Every block before the target is already where it belongs, so
+                // the block being moved is always somewhere after it
+                while self.state(target) == BlockState::Unusable {
+                    target += 1;
+                }
+
+                let current = self.chain(firsts[save])?.as_slice()[index];
+
+                if current != target {
+                    if self.state(target).in_use() {
+                        let spare = (target + 1..=SAVE_BLOCKS as u8)
+                            .find(|x| {
+                                let state = self.state(*x);
+                                !state.in_use() && state != BlockState::Unusable
+                            })
+                            .ok_or(Error::NoSpace)?;
+
+                        self.relocate(target, spare)?;
+                        Self::moved(&mut firsts, target, spare);
+                    }
+
+                    self.relocate(current, target)?;
+                    Self::moved(&mut firsts, current, target);
+                }
+
+                index += 1;
+                target += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn state(&self, block: u8) -> BlockState {
+        self.entries[usize::from(block) - 1].state()
+    }
+
+    /// Keep track of saves whose first block moved
+    fn moved(firsts: &mut [u8], from: u8, to: u8) {
+        for first in firsts.iter_mut().filter(|x| **x == from) {
+            *first = to;
+        }
+    }
+}
+
+/// Copy a save from one store to another, with the errors of both made to fit
+/// in with each other
+fn copy<A, B, E>(
+    source: &mut Filesystem<A>,
+    target: &mut Filesystem<B>,
+    first: u8,
+) -> Result<u8, Error<E>>
+where
+    A: Widen<E>,
+    B: Widen<E>,
+{
+    let chain = source.chain(first).map_err(A::widen)?;
+    let entry = *source.entry(first).ok_or(Error::NotFound)?;
+
+    let before = target.entries;
+    let blocks = target
+        .reserve(entry.filename(), chain.len() * BLOCK_SIZE)
+        .map_err(B::widen)?;
+
+    let mut frame: Frame = [0u8; FRAME_SIZE];
+    let mut copy = || {
+        for (from, to) in chain.as_slice().iter().zip(blocks.as_slice()) {
+            for x in 0..FRAMES_PER_BLOCK {
+                source
+                    .read(u16::from(*from) * FRAMES_PER_BLOCK + x, &mut frame)
+                    .map_err(A::widen)?;
+                target
+                    .write(u16::from(*to) * FRAMES_PER_BLOCK + x, &frame)
+                    .map_err(B::widen)?;
+            }
+        }
+
+        target.commit(&before).map_err(B::widen)
+    };
+
+    if let Err(x) = copy() {
+        target.entries = before;
+        return Err(x);
+    }
+
+    Ok(blocks.as_slice()[0])
+}
+
+impl<SPI, CS> PlayStationPort<SPI, CS>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+{
+    /// Copy a save between the memory cards on two ports of a multi-tap. Both
+    /// cards share this port so the save goes through `buffer`, which needs room
+    /// for the whole save and a frame more. The multi-tap port is put back the
+    /// way it was afterwards.
+    pub fn copy_save_between(
+        &mut self,
+        from: MultitapPort,
+        to: MultitapPort,
+        first: u8,
+        buffer: &mut [u8],
+    ) -> Result<u8, Error<SPI::Error>> {
+        let selected = self.multitap_port.clone();
+
+        self.multitap_port = from;
+        let result = Filesystem::open(self.memory_card())
+            .and_then(|mut x| x.export_save(first, SaveFormat::Mcs, buffer))
+            .and_then(|length| {
+                self.multitap_port = to;
+                Filesystem::open(self.memory_card())?.import_save(&buffer[..length])
+            });
+
+        self.multitap_port = selected;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::filesystem::{BlockState, Filesystem, BLOCK_SIZE};
+    use super::super::CARD_SIZE;
+    use crate::simulator::{Personality, Simulator};
+    use crate::{Error, MultitapPort, PlayStationPort};
+
+    #[test]
+    fn delete_and_undelete() {
+        let mut card = [0x55u8; CARD_SIZE];
+        let mut fs = Filesystem::format(&mut card).unwrap();
+        let mut buffer = [0u8; 2 * BLOCK_SIZE];
+
+        let block = fs
+            .write_save(b"BASLUS-00001", &[1; BLOCK_SIZE + 1])
+            .unwrap();
+        fs.delete(block).unwrap();
+        assert_eq!(fs.saves().count(), 0);
+        assert_eq!(fs.entry(2).unwrap().state(), BlockState::DeletedLast);
+        assert!(matches!(fs.delete(block), Err(Error::NotFound)));
+
+        let mut fs = Filesystem::open(fs.into_inner()).unwrap();
+        fs.undelete(block).unwrap();
+        fs.read_save(block, &mut buffer).unwrap();
+        assert_eq!(buffer[BLOCK_SIZE], 1);
+
+        // Once a block is reused, the save is gone for good
+        fs.delete(block).unwrap();
+        fs.write_save(b"BASLUS-00002", &[2; 14 * BLOCK_SIZE])
+            .unwrap();
+        assert!(matches!(fs.undelete(block), Err(Error::NotFound)));
+    }
+
+    #[test]
+    fn copy_between_cards() {
+        let mut card = [0u8; CARD_SIZE];
+        let mut other = [0u8; CARD_SIZE];
+        let mut fs = Filesystem::format(&mut card).unwrap();
+        let mut other_fs = Filesystem::format(&mut other).unwrap();
+        let mut buffer = [0u8; BLOCK_SIZE];
+
+        other_fs.write_save(b"BASLUS-00001", &[1; 10]).unwrap();
+        let block = fs.write_save(b"BESLES-00002", &[2; 10]).unwrap();
+        let block = other_fs.copy_save(&mut fs, block).unwrap();
+
+        assert_eq!(block, 2);
+        other_fs.read_save(block, &mut buffer).unwrap();
+        assert_eq!(buffer[9], 2);
+        assert_eq!(other_fs.find(b"BESLES-00002"), Some(2));
+    }
+
+    #[test]
+    fn copy_between_ports() {
+        let sim = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+        let mut buffer = [0u8; 128 + BLOCK_SIZE];
+
+        sim.insert_card();
+        let block = Filesystem::format(port.memory_card())
+            .unwrap()
+            .write_save(b"BISLPS-00001", &[7; 10])
+            .unwrap();
+
+        // A second copy on the same card
+        let block = port
+            .copy_save_between(MultitapPort::A, MultitapPort::A, block, &mut buffer)
+            .unwrap();
+        assert_eq!(block, 2);
+        assert_eq!(sim.card_frame(2 * 64)[9], 7);
+
+        sim.insert_card_on(MultitapPort::B);
+        port.set_multitap_port(MultitapPort::B);
+        Filesystem::format(port.memory_card()).unwrap();
+
+        let block = port
+            .copy_save_between(MultitapPort::A, MultitapPort::B, block, &mut buffer)
+            .unwrap();
+        assert_eq!(block, 1);
+        assert_eq!(sim.card_frame_on(MultitapPort::B, 64)[9], 7);
+        assert!(matches!(port.multitap_port, MultitapPort::B));
+    }
+
+    #[test]
+    fn copy_between_image_and_card() {
+        let sim = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+        let mut image = [0u8; CARD_SIZE];
+        let mut image_fs = Filesystem::format(&mut image).unwrap();
+
+        sim.insert_card();
+        let mut card = Filesystem::format(port.memory_card()).unwrap();
+
+        let block = image_fs.write_save(b"BASLUS-00001", &[3; 10]).unwrap();
+        let block = card.copy_save(&mut image_fs, block).unwrap();
+        assert_eq!(block, 1);
+        assert_eq!(sim.card_frame(64)[9], 3);
+
+        let block = card.copy_save_to(&mut image_fs, block).unwrap();
+        assert_eq!(block, 2);
+        assert_eq!(image_fs.saves().count(), 2);
+    }
+
+    #[test]
+    fn defragment() {
+        let mut card = [0u8; CARD_SIZE];
+        let mut fs = Filesystem::format(&mut card).unwrap();
+        let mut buffer = [0u8; 2 * BLOCK_SIZE];
+
+        let first = fs.write_save(b"A", &[0xa; BLOCK_SIZE]).unwrap();
+        fs.write_save(b"B", &[0xb; 2 * BLOCK_SIZE]).unwrap();
+        fs.write_save(b"C", &[0xc; BLOCK_SIZE]).unwrap();
+        fs.free(first).unwrap();
+
+        let mut data = [0xd; 2 * BLOCK_SIZE];
+        data[BLOCK_SIZE..].copy_from_slice(&[0xe; BLOCK_SIZE]);
+        fs.write_save(b"D", &data).unwrap();
+        assert_eq!(fs.chain(1).unwrap().as_slice(), &[1, 5]);
+
+        fs.defragment().unwrap();
+
+        let mut fs = Filesystem::open(fs.into_inner()).unwrap();
+        assert_eq!(fs.chain(1).unwrap().as_slice(), &[1, 2]);
+        assert_eq!(fs.chain(3).unwrap().as_slice(), &[3, 4]);
+        assert_eq!(fs.chain(5).unwrap().as_slice(), &[5]);
+        assert_eq!(fs.find(b"C"), Some(5));
+
+        fs.read_save(1, &mut buffer).unwrap();
+        assert_eq!(buffer[..], data[..]);
+        fs.read_save(3, &mut buffer).unwrap();
+        assert!(buffer.iter().all(|x| *x == 0xb));
+    }
+}
@@ -0,0 +1,220 @@
+//! Syncing Cards and Images
+//! ============================
+//! Writing a whole card over the controller port takes minutes, and most of the
+//! time only a save or two has changed. `push` only writes the frames of a card
+//! that differ from an image, and `pull` does the same the other way to keep a
+//! backup up to date.
+//!
+//! Blocks the directory says are free are skipped, as whatever is in them
+//! doesn't matter. Deleted saves are kept so they can still be brought back.
+//!
+//! ```
+//! use pscontroller_rs::memorycard::filesystem::Filesystem;
+//! use pscontroller_rs::memorycard::sync::push;
+//! use pscontroller_rs::memorycard::CARD_SIZE;
+//!
+//! let mut image = [0u8; CARD_SIZE];
+//! let mut card = [0u8; CARD_SIZE];
+//! Filesystem::format(&mut image).unwrap().write_save(b"BASLUS-00001", &[1; 10]).unwrap();
+//!
+//! let summary = push(&mut image, &mut card, |progress| {
+//!     assert!(progress.done <= progress.total);
+//! })
+//! .unwrap();
+//! assert_eq!(summary.compared, 128);
+//! assert_eq!(&image[..], &card[..]);
+//! ```
+
+use super::filesystem::{BlockState, DirectoryEntry, DIRECTORY_FRAME, FRAMES_PER_BLOCK};
+use super::{Frame, FrameStore, FRAME_SIZE};
+use crate::Error;
+use core::convert::Infallible;
+
+const BLOCKS: u16 = 16;
+
+/// How far along a sync is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    /// Frames looked at so far
+    pub done: usize,
+    /// Frames that will be looked at in all
+    pub total: usize,
+    /// Frames that were different and had to be written
+    pub written: usize,
+}
+
+/// What a sync ended up doing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    /// Frames that were compared
+    pub compared: usize,
+    /// Frames that were different and had to be written
+    pub written: usize,
+}
+
+/// Work out which blocks have anything worth keeping. The first block always does.
+fn used_blocks<S: FrameStore>(store: &mut S) -> Result<[bool; BLOCKS as usize], Error<S::Error>> {
+    let mut used = [true; BLOCKS as usize];
+    let mut frame: Frame = [0u8; FRAME_SIZE];
+
+    for (x, used) in used.iter_mut().enumerate().skip(1) {
+        store.read_frame(DIRECTORY_FRAME + x as u16 - 1, &mut frame)?;
+        *used = DirectoryEntry::from_frame(frame).state() != BlockState::Free;
+    }
+
+    Ok(used)
+}
+
+/// Frames of the used blocks, with the first block last so the directory is
+/// only updated once the saves it points to are in place
+fn frames(used: [bool; BLOCKS as usize]) -> impl Iterator<Item = u16> {
+    (1..BLOCKS)
+        .chain(Some(0))
+        .filter(move |x| used[usize::from(*x)])
+        .flat_map(|x| x * FRAMES_PER_BLOCK..(x + 1) * FRAMES_PER_BLOCK)
+}
+
+/// Make a card match an image, only writing frames that are different. Every
+/// frame written is read back to make sure it took.
+pub fn push<I, C>(
+    image: &mut I,
+    card: &mut C,
+    mut progress: impl FnMut(Progress),
+) -> Result<Summary, Error<C::Error>>
+where
+    I: FrameStore<Error = Infallible>,
+    C: FrameStore,
+{
+    let used = used_blocks(image).map_err(Error::widen)?;
+    let mut status = Progress {
+        done: 0,
+        total: frames(used).count(),
+        written: 0,
+    };
+
+    let mut wanted: Frame = [0u8; FRAME_SIZE];
+    let mut current: Frame = [0u8; FRAME_SIZE];
+
+    for frame in frames(used) {
+        image.read_frame(frame, &mut wanted).map_err(Error::widen)?;
+
+        let same = match card.read_frame(frame, &mut current) {
+            Ok(()) => current[..] == wanted[..],
+            // Worth a try, a fresh write might fix it
+            Err(Error::BadChecksum) => false,
+            Err(x) => return Err(x),
+        };
+
+        if !same {
+            card.write_frame(frame, &wanted)?;
+            card.read_frame(frame, &mut current)?;
+
+            if current[..] != wanted[..] {
+                return Err(Error::VerifyFailed);
+            }
+
+            status.written += 1;
+        }
+
+        status.done += 1;
+        progress(status);
+    }
+
+    Ok(Summary {
+        compared: status.done,
+        written: status.written,
+    })
+}
+
+/// Make an image match a card. Only the blocks the card is using are read.
+pub fn pull<C, I>(
+    card: &mut C,
+    image: &mut I,
+    mut progress: impl FnMut(Progress),
+) -> Result<Summary, Error<C::Error>>
+where
+    C: FrameStore,
+    I: FrameStore<Error = Infallible>,
+{
+    let used = used_blocks(card)?;
+    let mut status = Progress {
+        done: 0,
+        total: frames(used).count(),
+        written: 0,
+    };
+
+    let mut wanted: Frame = [0u8; FRAME_SIZE];
+    let mut current: Frame = [0u8; FRAME_SIZE];
+
+    for frame in frames(used) {
+        card.read_frame(frame, &mut wanted)?;
+        image
+            .read_frame(frame, &mut current)
+            .map_err(Error::widen)?;
+
+        if current[..] != wanted[..] {
+            image.write_frame(frame, &wanted).map_err(Error::widen)?;
+            status.written += 1;
+        }
+
+        status.done += 1;
+        progress(status);
+    }
+
+    Ok(Summary {
+        compared: status.done,
+        written: status.written,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::filesystem::{Filesystem, BLOCK_SIZE};
+    use super::super::CARD_SIZE;
+    use super::{pull, push, Progress};
+    use crate::simulator::{Personality, Simulator};
+    use crate::PlayStationPort;
+
+    #[test]
+    fn sync_with_card() {
+        let sim = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+        let mut image = [0u8; CARD_SIZE];
+        let mut backup = [0u8; CARD_SIZE];
+        let mut last = None;
+
+        sim.insert_card();
+        let mut fs = Filesystem::format(&mut image).unwrap();
+        fs.write_save(b"BASLUS-00001", &[1; 2 * BLOCK_SIZE])
+            .unwrap();
+
+        let summary = push(&mut image, &mut port.memory_card(), |x| last = Some(x)).unwrap();
+        assert_eq!(summary.compared, 3 * 64);
+        // Only the header, directory and broken frame list of the first block
+        // aren't zeros like the blank card
+        assert_eq!(summary.written, 2 * 64 + 2 + 15 + 20);
+        assert_eq!(
+            last,
+            Some(Progress {
+                done: 192,
+                total: 192,
+                written: summary.written
+            })
+        );
+
+        // Nothing changed, so nothing gets written
+        let summary = push(&mut image, &mut port.memory_card(), |_| {}).unwrap();
+        assert_eq!(summary.written, 0);
+
+        // One frame changes on the card and only that comes back
+        let mut frame = sim.card_frame(64 + 10);
+        frame[0] = 0x42;
+        sim.set_card_frame(64 + 10, &frame);
+
+        pull(&mut port.memory_card(), &mut backup, |_| {}).unwrap();
+        let summary = pull(&mut port.memory_card(), &mut image, |_| {}).unwrap();
+        assert_eq!(summary.written, 1);
+        assert_eq!(image[..], backup[..]);
+        assert_eq!(image[(64 + 10) * 128], 0x42);
+    }
+}
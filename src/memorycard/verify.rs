@@ -0,0 +1,368 @@
+//! Checking and Repairing Cards
+//! ============================
+//! Old cards go bad in all sorts of ways. Frames wear out and stop reading back
+//! the way they were written, directory entries get half written, and saves end
+//! up pointing into each other. `verify` reads every frame of a card and reports
+//! all of it, and the repair functions fix what can be fixed.
+//!
+//! ```
+//! use pscontroller_rs::memorycard::filesystem::Filesystem;
+//! use pscontroller_rs::memorycard::verify::verify;
+//! use pscontroller_rs::memorycard::CARD_SIZE;
+//!
+//! let mut card = [0u8; CARD_SIZE];
+//! Filesystem::format(&mut card).unwrap();
+//!
+//! let report = verify(&mut card).unwrap();
+//! assert!(report.is_clean());
+//! ```
+
+use super::filesystem::{
+    checksum, BlockState, DirectoryEntry, BROKEN_COUNT, BROKEN_LIST_FRAME, CHECKSUM_OFFSET,
+    DIRECTORY_FRAME, FRAMES_PER_BLOCK, HEADER_COPY_FRAME, HEADER_MAGIC, NO_BROKEN_FRAME,
+    REPLACEMENT_FRAME, SAVE_BLOCKS,
+};
+use super::{Frame, FrameStore, FRAME_COUNT, FRAME_SIZE};
+use crate::Error;
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Some of the 15 save blocks, numbered 1 to 15
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BlockSet {
+    bits: u16,
+}
+
+impl BlockSet {
+    fn insert(&mut self, block: u8) {
+        self.bits |= 1 << block;
+    }
+
+    /// Whether a block is in the set
+    pub fn contains(&self, block: u8) -> bool {
+        block < 16 && self.bits & (1 << block) != 0
+    }
+
+    /// How many blocks are in the set
+    pub fn len(&self) -> usize {
+        self.bits.count_ones() as usize
+    }
+
+    /// Whether the set has nothing in it
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// The blocks in the set, lowest first
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (1..=SAVE_BLOCKS as u8).filter(move |x| self.contains(*x))
+    }
+}
+
+/// Some of the frames on a card
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameSet {
+    bits: [u32; FRAME_COUNT as usize / 32],
+}
+
+impl FrameSet {
+    fn insert(&mut self, frame: u16) {
+        self.bits[usize::from(frame / 32)] |= 1 << (frame % 32);
+    }
+
+    /// Whether a frame is in the set
+    pub fn contains(&self, frame: u16) -> bool {
+        frame < FRAME_COUNT && self.bits[usize::from(frame / 32)] & (1 << (frame % 32)) != 0
+    }
+
+    /// How many frames are in the set
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|x| x.count_ones() as usize).sum()
+    }
+
+    /// Whether the set has nothing in it
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|x| *x == 0)
+    }
+
+    /// The frames in the set, lowest first
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..FRAME_COUNT).filter(move |x| self.contains(*x))
+    }
+}
+
+/// Everything that's wrong with a card
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Report {
+    /// Whether frame 0 starts with "MC" and its checksum is good
+    pub header_valid: bool,
+    /// Frames that came back with a bad checksum or that the card refused to read
+    pub unreadable: FrameSet,
+    /// Blocks whose directory entry has a bad checksum
+    pub bad_checksums: BlockSet,
+    /// Blocks that more than one save claims. The first save to claim the
+    /// block keeps it, the others are reported here.
+    pub cross_linked: BlockSet,
+    /// Blocks that say they're the middle or end of a save, but no save leads to
+    pub orphaned: BlockSet,
+    /// First blocks of saves whose links go nowhere sensible
+    pub broken_chains: BlockSet,
+    /// The card's own list of frames that went bad, each of which has been
+    /// replaced by one of the spare frames
+    pub broken_frames: [Option<u32>; BROKEN_COUNT],
+}
+
+impl Report {
+    /// Whether nothing at all is wrong. Frames the card already replaced don't
+    /// count as anything wrong.
+    pub fn is_clean(&self) -> bool {
+        self.header_valid
+            && self.unreadable.is_empty()
+            && self.bad_checksums.is_empty()
+            && self.cross_linked.is_empty()
+            && self.orphaned.is_empty()
+            && self.broken_chains.is_empty()
+    }
+}
+
+/// Read every frame of a card and work out what's wrong with it. Frames that
+/// won't read are noted in the report, but any other error stops the check.
+pub fn verify<S: FrameStore>(store: &mut S) -> Result<Report, Error<S::Error>> {
+    let mut report = Report::default();
+    let mut entries = [DirectoryEntry::free(); SAVE_BLOCKS];
+    let mut data: Frame = [0u8; FRAME_SIZE];
+
+    for frame in 0..FRAME_COUNT {
+        match store.read_frame(frame, &mut data) {
+            Ok(()) => {}
+            Err(Error::BadChecksum) | Err(Error::BadSector) => {
+                report.unreadable.insert(frame);
+                continue;
+            }
+            Err(x) => return Err(x),
+        }
+
+        match frame {
+            0 => {
+                report.header_valid =
+                    data.starts_with(HEADER_MAGIC) && checksum(&data) == data[CHECKSUM_OFFSET];
+            }
+            x if (DIRECTORY_FRAME..DIRECTORY_FRAME + SAVE_BLOCKS as u16).contains(&x) => {
+                let block = (x - DIRECTORY_FRAME) as u8 + 1;
+                let entry = DirectoryEntry::from_frame(data);
+
+                if !entry.checksum_valid() {
+                    report.bad_checksums.insert(block);
+                }
+
+                entries[usize::from(block) - 1] = entry;
+            }
+            x if (BROKEN_LIST_FRAME..BROKEN_LIST_FRAME + BROKEN_COUNT as u16).contains(&x) => {
+                let broken = LittleEndian::read_u32(&data[0..4]);
+
+                if broken != NO_BROKEN_FRAME {
+                    report.broken_frames[usize::from(x - BROKEN_LIST_FRAME)] = Some(broken);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    check_chains(&entries, &mut report);
+
+    Ok(report)
+}
+
+/// Follow every save through the directory and see who ends up where
+fn check_chains(entries: &[DirectoryEntry; SAVE_BLOCKS], report: &mut Report) {
+    let mut owners = [0u8; SAVE_BLOCKS];
+    let state = |block: u8| entries[usize::from(block) - 1].state();
+
+    for first in (1..=SAVE_BLOCKS as u8).filter(|x| state(*x) == BlockState::First) {
+        let mut next = Some(first);
+
+        while let Some(block) = next {
+            if block == 0 || usize::from(block) > SAVE_BLOCKS {
+                report.broken_chains.insert(first);
+                break;
+            }
+
+            let owner = &mut owners[usize::from(block) - 1];
+
+            if *owner == first {
+                // Going around in circles
+                report.broken_chains.insert(first);
+                break;
+            }
+
+            if *owner != 0 {
+                report.cross_linked.insert(block);
+                break;
+            }
+
+            if block != first && !matches!(state(block), BlockState::Middle | BlockState::Last) {
+                report.broken_chains.insert(first);
+                break;
+            }
+
+            *owner = first;
+            next = entries[usize::from(block) - 1].next();
+        }
+    }
+
+    for block in 1..=SAVE_BLOCKS as u8 {
+        let linked = matches!(state(block), BlockState::Middle | BlockState::Last);
+
+        if linked && owners[usize::from(block) - 1] == 0 {
+            report.orphaned.insert(block);
+        }
+    }
+}
+
+/// Fix the checksums of the header, the directory and the list of broken
+/// frames. Returns how many frames needed fixing. The contents are left as they
+/// are, so run `verify` again to see whether they make sense.
+pub fn rebuild_checksums<S: FrameStore>(store: &mut S) -> Result<usize, Error<S::Error>> {
+    let mut data: Frame = [0u8; FRAME_SIZE];
+    let mut fixed = 0;
+
+    let frames = (0..BROKEN_LIST_FRAME + BROKEN_COUNT as u16).chain(Some(HEADER_COPY_FRAME));
+
+    for frame in frames {
+        store.read_frame(frame, &mut data)?;
+
+        let sum = checksum(&data);
+        if data[CHECKSUM_OFFSET] != sum {
+            data[CHECKSUM_OFFSET] = sum;
+            store.write_frame(frame, &data)?;
+            fixed += 1;
+        }
+    }
+
+    Ok(fixed)
+}
+
+/// Stop using a frame of a save that's gone bad and use one of the card's spare
+/// frames in its place. Whatever can still be read from the frame is moved over,
+/// even with a bad checksum. If the card won't read it at all, the spare starts
+/// out as zeros. Returns the spare frame now used.
+///
+/// Only frames of save blocks can be replaced.
+pub fn remap_frame<S: FrameStore>(store: &mut S, frame: u16) -> Result<u16, Error<S::Error>> {
+    let mut slot = None;
+    let mut data: Frame = [0u8; FRAME_SIZE];
+
+    if !(FRAMES_PER_BLOCK..FRAME_COUNT).contains(&frame) {
+        return Err(Error::Unsupported);
+    }
+
+    for x in 0..BROKEN_COUNT as u16 {
+        store.read_frame(BROKEN_LIST_FRAME + x, &mut data)?;
+
+        match LittleEndian::read_u32(&data[0..4]) {
+            // Already done
+            y if y == u32::from(frame) => return Ok(REPLACEMENT_FRAME + x),
+            NO_BROKEN_FRAME if slot.is_none() => slot = Some(x),
+            _ => {}
+        }
+    }
+
+    let slot = slot.ok_or(Error::NoSpace)?;
+
+    match store.read_frame(frame, &mut data) {
+        Ok(()) => {}
+        Err(Error::BadChecksum) => {}
+        Err(Error::BadSector) => data = [0u8; FRAME_SIZE],
+        Err(x) => return Err(x),
+    }
+    store.write_frame(REPLACEMENT_FRAME + slot, &data)?;
+
+    // Only point at the spare once it has the data in it
+    let mut entry: Frame = [0u8; FRAME_SIZE];
+    LittleEndian::write_u32(&mut entry[0..4], u32::from(frame));
+    LittleEndian::write_u16(&mut entry[8..10], 0xffff);
+    entry[CHECKSUM_OFFSET] = checksum(&entry);
+    store.write_frame(BROKEN_LIST_FRAME + slot, &entry)?;
+
+    Ok(REPLACEMENT_FRAME + slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::filesystem::{Filesystem, BLOCK_SIZE};
+    use super::{rebuild_checksums, remap_frame, verify};
+    use crate::simulator::{Personality, Simulator};
+    use crate::{Error, PlayStationPort};
+
+    #[test]
+    fn worn_frames() {
+        let sim = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+        let mut buffer = [0u8; BLOCK_SIZE];
+
+        sim.insert_card();
+        Filesystem::format(port.memory_card())
+            .unwrap()
+            .write_save(b"BASLUS-00001", &[0x77; BLOCK_SIZE])
+            .unwrap();
+        sim.set_worn_frame(Some(64 + 3));
+
+        let report = verify(&mut port.memory_card()).unwrap();
+        assert!(!report.is_clean());
+        assert!(report.unreadable.iter().eq([67].iter().cloned()));
+
+        // What came back is kept, bad checksum or not
+        assert_eq!(remap_frame(&mut port.memory_card(), 67).unwrap(), 36);
+        assert_eq!(remap_frame(&mut port.memory_card(), 67).unwrap(), 36);
+        assert_eq!(
+            sim.card_frame(16)[..10],
+            [67, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff]
+        );
+        assert!(matches!(
+            remap_frame(&mut port.memory_card(), 0),
+            Err(Error::Unsupported)
+        ));
+
+        let mut fs = Filesystem::open(port.memory_card()).unwrap();
+        fs.read_save(1, &mut buffer).unwrap();
+        assert_eq!(buffer[3 * 128 - 1], 0x77);
+        assert_eq!(buffer[3 * 128], 0x77);
+
+        sim.set_worn_frame(None);
+        let report = verify(&mut port.memory_card()).unwrap();
+        assert_eq!(report.broken_frames[0], Some(67));
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn broken_directory() {
+        let sim = Simulator::new(Personality::DualShock);
+        let mut port = PlayStationPort::new(sim.bus(), Some(sim.select()));
+
+        sim.insert_card();
+        let mut fs = Filesystem::format(port.memory_card()).unwrap();
+        fs.write_save(b"BASLUS-00001", &[1; 2 * BLOCK_SIZE])
+            .unwrap();
+        fs.write_save(b"BASLUS-00002", &[2; 2 * BLOCK_SIZE])
+            .unwrap();
+
+        // Point the end of the first save into the second, and leave a stray block about
+        let mut entry = sim.card_frame(2);
+        entry[8..10].copy_from_slice(&[3, 0]);
+        sim.set_card_frame(2, &entry);
+
+        let mut entry = sim.card_frame(5);
+        entry[0] = 0x53;
+        sim.set_card_frame(5, &entry);
+
+        let report = verify(&mut port.memory_card()).unwrap();
+        assert!(report.header_valid);
+        assert!(report.bad_checksums.iter().eq([2, 5].iter().cloned()));
+        assert!(report.cross_linked.iter().eq([4].iter().cloned()));
+        assert!(report.orphaned.iter().eq([5].iter().cloned()));
+        assert!(report.broken_chains.is_empty());
+
+        assert_eq!(rebuild_checksums(&mut port.memory_card()).unwrap(), 2);
+        let report = verify(&mut port.memory_card()).unwrap();
+        assert!(report.bad_checksums.is_empty());
+    }
+}
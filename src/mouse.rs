@@ -4,8 +4,13 @@
 //! for the PlayStation. This was implemented from notes online and while it
 //! should be accurate, it has not been tested.
 
+use super::decoder::Decoder;
+use super::deviceid::DeviceId;
+use super::{check_length, ControllerData, DecodeError, Device};
+use byteorder::{ByteOrder, LittleEndian};
+use core::convert::TryFrom;
+
 /// The two buttons found on the mouse
-#[repr(C)]
 #[derive(Copy, Clone)]
 pub struct MouseButtons {
     data: u16,
@@ -15,6 +20,12 @@ impl MouseButtons {
     const PM_L: u16 = 0x0800;
     const PM_R: u16 = 0x0400;
 
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            data: LittleEndian::read_u16(bytes),
+        }
+    }
+
     /// A button on the controller
     pub fn left(&self) -> bool {
         self.data & Self::PM_L == 0
@@ -26,7 +37,6 @@ impl MouseButtons {
     }
 }
 
-#[repr(C)]
 #[derive(Copy, Clone)]
 /// Represents the classic Controller
 pub struct Mouse {
@@ -37,3 +47,30 @@ pub struct Mouse {
     /// Difference in X-Axis since last poll
     pub x: i8,
 }
+
+impl TryFrom<&[u8]> for Mouse {
+    type Error = DecodeError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        check_length(data, 4)?;
+
+        Ok(Self {
+            buttons: MouseButtons::from_bytes(data),
+            y: data[2] as i8,
+            x: data[3] as i8,
+        })
+    }
+}
+
+/// Decodes the PlayStation Mouse
+pub struct MouseDecoder;
+
+impl Decoder for MouseDecoder {
+    fn claims(&self, id: DeviceId, _: &ControllerData) -> bool {
+        id == DeviceId::MOUSE
+    }
+
+    fn decode(&self, _: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        Ok(Device::Mouse(data.decode()?))
+    }
+}
@@ -10,8 +10,13 @@
 //! compatibility mode. It will work this way if the 'mode' button is held when the
 //! controller is powered on or plugged in.
 
+use super::decoder::Decoder;
+use super::deviceid::DeviceId;
+use super::{check_length, ControllerData, DecodeError, Device};
+use byteorder::{ByteOrder, LittleEndian};
+use core::convert::TryFrom;
+
 /// The digital buttons of the Namco NegCon
-#[repr(C)]
 #[derive(Copy, Clone)]
 pub struct NegconButtons {
     data: u16,
@@ -32,6 +37,12 @@ impl NegconButtons {
     const NC_B: u16 = 0x1000;
     const NC_A: u16 = 0x2000;
 
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            data: LittleEndian::read_u16(bytes),
+        }
+    }
+
     /// A button on the controller
     pub fn select(&self) -> bool {
         self.data & Self::NC_SELECT == 0
@@ -84,7 +95,6 @@ impl NegconButtons {
     }
 }
 
-#[repr(C)]
 #[derive(Copy, Clone)]
 /// Represents the Namco NegCon controller
 pub struct NegCon {
@@ -103,3 +113,32 @@ pub struct NegCon {
     /// Position of switch L
     pub switchl: u8,
 }
+
+impl TryFrom<&[u8]> for NegCon {
+    type Error = DecodeError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        check_length(data, 6)?;
+
+        Ok(Self {
+            buttons: NegconButtons::from_bytes(data),
+            twist: data[2],
+            switchi: data[3],
+            switchii: data[4],
+            switchl: data[5],
+        })
+    }
+}
+
+/// Decodes the neGcon
+pub struct NegConDecoder;
+
+impl Decoder for NegConDecoder {
+    fn claims(&self, id: DeviceId, _: &ControllerData) -> bool {
+        id == DeviceId::NEGCON
+    }
+
+    fn decode(&self, _: DeviceId, data: &ControllerData) -> Result<Device, DecodeError> {
+        Ok(Device::NegCon(data.decode()?))
+    }
+}
@@ -0,0 +1,184 @@
+//! Sony PocketStation
+//! ============================
+//! A tiny handheld that plugs into a memory card slot. To the console it's a
+//! memory card with a few extra commands: it has a clock, it can run the
+//! applications stored in its flash, and the flash is the card's frames, so
+//! saves and applications live side by side.
+//!
+//! The extra commands follow the no$psx notes on the PocketStation's kernel.
+//! Nobody has pointed this at a real one yet, so treat the field layouts with
+//! some suspicion and please open an issue if yours disagrees.
+
+use super::memorycard::{CardId, Flags, Frame, MemoryCard};
+use super::Error;
+use hal::digital::OutputPin;
+use hal::spi::SpiBus;
+
+const CMD_GET_VERSION: u8 = 0x58;
+const CMD_PREPARE_EXECUTION: u8 = 0x59;
+const CMD_GET_STATUS: u8 = 0x5a;
+
+const VERSION_LENGTH: usize = 9;
+const EXECUTE_LENGTH: usize = 11;
+const EXECUTE_ARGUMENTS: u8 = 6;
+const STATUS_LENGTH: usize = 23;
+const STATUS_ARGUMENTS: u8 = 18;
+
+/// Turn a binary coded decimal byte into what it means
+fn bcd(x: u8) -> u8 {
+    (x >> 4) * 10 + (x & 0x0f)
+}
+
+/// The date according to the PocketStation's clock
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Date {
+    /// The year, all four digits of it
+    pub year: u16,
+    /// The month, from 1 to 12
+    pub month: u8,
+    /// The day of the month, from 1 to 31
+    pub day: u8,
+}
+
+/// The time according to the PocketStation's clock
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Time {
+    /// Hours, from 0 to 23
+    pub hour: u8,
+    /// Minutes, from 0 to 59
+    pub minute: u8,
+    /// Seconds, from 0 to 59
+    pub second: u8,
+    /// Day of the week, from 0 for Sunday to 6 for Saturday
+    pub weekday: u8,
+}
+
+/// What the PocketStation is up to.
+///
+/// Where each field sits in the reply is provisional: it's worked out from the
+/// no$psx notes and hasn't been checked against a real PocketStation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Status {
+    /// The directory block of the application that's running, or 0 for the
+    /// PocketStation's own menu
+    pub application: u16,
+    /// Communication flags the application uses to talk to games
+    pub flags: u32,
+    /// The PocketStation's serial number
+    pub serial: u32,
+    /// Today's date
+    pub date: Date,
+    /// The time
+    pub time: Time,
+}
+
+/// A PocketStation sitting on a `PlayStationPort`. Get one with
+/// `PlayStationPort::pocketstation`.
+pub struct PocketStation<'a, SPI, CS> {
+    card: MemoryCard<'a, SPI, CS>,
+}
+
+impl<'a, SPI, CS> PocketStation<'a, SPI, CS>
+where
+    SPI: SpiBus,
+    CS: OutputPin,
+{
+    /// Make sure the memory card really is a PocketStation. It answers the ID
+    /// command with the same 1024 frames of 128 bytes as a plain memory card, so
+    /// the ID can't tell them apart. Only the PocketStation acknowledges the
+    /// version command, which is what gives it away.
+    pub(crate) fn detect(mut card: MemoryCard<'a, SPI, CS>) -> Result<Self, Error<SPI::Error>> {
+        let mut buffer = [0u8; VERSION_LENGTH];
+
+        match card.transact(&[0, CMD_GET_VERSION], &mut buffer) {
+            Ok(_) => Ok(Self { card }),
+            Err(Error::NotPresent) | Err(Error::BadResponse) => Err(Error::Unsupported),
+            Err(x) => Err(x),
+        }
+    }
+
+    /// Ask what the PocketStation is doing and what time it thinks it is
+    pub fn status(&mut self) -> Result<Status, Error<SPI::Error>> {
+        let mut buffer = [0u8; STATUS_LENGTH];
+
+        self.card.transact(&[0, CMD_GET_STATUS], &mut buffer)?;
+
+        if buffer[4] != STATUS_ARGUMENTS {
+            return Err(Error::BadLength);
+        }
+
+        let field =
+            |x: usize| u32::from_le_bytes([buffer[x], buffer[x + 1], buffer[x + 2], buffer[x + 3]]);
+
+        Ok(Status {
+            application: u16::from_le_bytes([buffer[5], buffer[6]]),
+            flags: field(7),
+            serial: field(11),
+            date: Date {
+                day: bcd(buffer[15]),
+                month: bcd(buffer[16]),
+                year: u16::from(bcd(buffer[18])) * 100 + u16::from(bcd(buffer[17])),
+            },
+            time: Time {
+                second: bcd(buffer[19]),
+                minute: bcd(buffer[20]),
+                hour: bcd(buffer[21]),
+                weekday: bcd(buffer[22]),
+            },
+        })
+    }
+
+    /// The date and time on the PocketStation's clock
+    pub fn clock(&mut self) -> Result<(Date, Time), Error<SPI::Error>> {
+        let status = self.status()?;
+
+        Ok((status.date, status.time))
+    }
+
+    /// The directory block of the application that's running, if any
+    pub fn application(&mut self) -> Result<Option<u16>, Error<SPI::Error>> {
+        let status = self.status()?;
+
+        Ok(match status.application {
+            0 => None,
+            x => Some(x),
+        })
+    }
+
+    /// Start the application saved at a directory block, handing it a parameter
+    /// of its choosing. The application has to be on the PocketStation already.
+    pub fn launch(&mut self, application: u16, parameter: u32) -> Result<(), Error<SPI::Error>> {
+        let mut buffer = [0u8; EXECUTE_LENGTH];
+        let mut command = [0u8; EXECUTE_LENGTH];
+
+        command[1] = CMD_PREPARE_EXECUTION;
+        command[4] = EXECUTE_ARGUMENTS;
+        command[5..7].copy_from_slice(&application.to_le_bytes());
+        command[7..11].copy_from_slice(&parameter.to_le_bytes());
+
+        self.card.transact(&command, &mut buffer)?;
+
+        Ok(())
+    }
+
+    /// How big the flash is
+    pub fn read_id(&mut self) -> Result<CardId, Error<SPI::Error>> {
+        self.card.read_id()
+    }
+
+    /// Read a frame of flash
+    pub fn read_flash(&mut self, frame: u16, data: &mut Frame) -> Result<Flags, Error<SPI::Error>> {
+        self.card.read_frame(frame, data)
+    }
+
+    /// Write a frame of flash
+    pub fn write_flash(&mut self, frame: u16, data: &Frame) -> Result<Flags, Error<SPI::Error>> {
+        self.card.write_frame(frame, data)
+    }
+
+    /// Treat the PocketStation like the memory card it also is, such as to put
+    /// a `Filesystem` on top of it
+    pub fn into_card(self) -> MemoryCard<'a, SPI, CS> {
+        self.card
+    }
+}
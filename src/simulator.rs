@@ -0,0 +1,905 @@
+//! Simulated Controller Bus
+//! ============================
+//! An in-memory stand-in for a controller sitting on the other end of the SPI bus.
+//! It speaks the same protocol as the real thing, bit-reversed bytes and all, so
+//! `PlayStationPort` can be exercised without any hardware attached. It's only
+//! built for the crate's own tests, or with the `simulator` feature.
+//!
+//! The behaviour of each `Personality` follows the dumps in `research/README.md`.
+//! Where the notes are silent, the simulator errs on the side of not answering,
+//! which is what most of the older devices do anyway.
+//!
+//! A memory card can be slotted in next to the controller with `insert_card`. It
+//! starts out blank and holds a full 128 KiB, so mind the stack. There's room for
+//! a second card on port B of a multi-tap, with `insert_card_on`.
+//!
+//! ```
+//! use pscontroller_rs::simulator::{Personality, Simulator};
+//! use pscontroller_rs::{Device, PlayStationPort};
+//!
+//! let pad = Simulator::new(Personality::DualShock2);
+//! let mut port = PlayStationPort::new(pad.bus(), Some(pad.select()));
+//!
+//! port.enable_pressure().unwrap();
+//!
+//! match port.read_input(None).unwrap() {
+//!     Device::DualShock2(_) => {}
+//!     _ => panic!("expected a DualShock 2"),
+//! }
+//! ```
+
+use crate::memorycard::{Flags, Frame, CARD_SIZE, FRAME_COUNT, FRAME_SIZE};
+use crate::MultitapPort;
+use bit_reverse::ParallelReverse;
+use core::cell::RefCell;
+use core::convert::Infallible;
+use hal::digital::{self, OutputPin};
+use hal::spi::{self, SpiBus};
+
+/// Address the controller answers to (port A, or no multitap)
+const PAD_ADDRESS: u8 = 0x01;
+/// Address the memory card answers to, with the multi-tap port added on
+const CARD_ADDRESS: u8 = 0x80;
+/// Memory card slots, on multi-tap ports A and B
+const CARD_SLOTS: usize = 2;
+/// Bytes of a single transaction the simulator keeps track of. Long enough
+/// for a memory card read.
+const TRANSACTION_LEN: usize = 140;
+/// Bytes of a pad's reply the simulator bothers working out
+const PAYLOAD_LEN: usize = 32;
+/// Length of the command header
+const HEADER_LEN: usize = 3;
+/// Length of every reply while in escape mode
+const CONFIG_LEN: usize = 6;
+/// The largest set of input bytes a device can report (DualShock 2 with pressures)
+pub const INPUT_LEN: usize = 18;
+
+/// Default response format once analog mode is on: buttons and both sticks
+const FORMAT_ANALOG: [u8; 3] = [0x3f, 0x00, 0x00];
+/// Response format covering every input byte of a DualShock 2
+const FORMAT_MASK: u32 = 0x0003_ffff;
+/// Where the pressures start in the input bytes
+const PRESSURE_START: usize = 6;
+/// Every pressure measured, which is how the DualShock 2 powers on
+const PRESSURES_ALL: u16 = 0x0fff;
+
+/// The device plugged into the simulated port. Each one answers the same way
+/// the matching hardware did in `research/README.md`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Personality {
+    /// Nothing plugged in. Every byte reads back as the pulled-up `0xFF`
+    Missing,
+    /// The original digital Controller
+    Classic,
+    /// DualShock (SCPH-1200)
+    DualShock,
+    /// DualShock 2 (SCPH-10010)
+    DualShock2,
+    /// Namco JogCon
+    JogCon,
+    /// RedOctane Guitar Hero controller
+    GuitarHero,
+    /// Namco NeGcon
+    NegCon,
+    /// Namco GunCon
+    GunCon,
+    /// PlayStation Mouse
+    Mouse,
+    /// A multitap with nothing plugged into the port being polled. It answers
+    /// for itself with an ID of 0x80, then nothing but the idle bus.
+    Multitap,
+}
+
+impl Personality {
+    /// Whether the device understands the 0x43 escape command
+    fn has_escape(self) -> bool {
+        matches!(
+            self,
+            Personality::DualShock
+                | Personality::DualShock2
+                | Personality::JogCon
+                | Personality::GuitarHero
+        )
+    }
+
+    /// What the device reports when nothing is being pressed
+    fn idle_input(self) -> [u8; INPUT_LEN] {
+        let mut input = [0u8; INPUT_LEN];
+        input[0] = 0xff;
+        input[1] = 0xff;
+
+        match self {
+            Personality::DualShock | Personality::DualShock2 | Personality::GuitarHero => {
+                input[2..6].copy_from_slice(&[0x80, 0x80, 0x80, 0x80]);
+            }
+            Personality::NegCon => input[2] = 0x80,
+            // "If the GunCon can't find the screen, it will send out X:10,Y:1"
+            Personality::GunCon => input[2..6].copy_from_slice(&[0x0a, 0x00, 0x01, 0x00]),
+            _ => {}
+        }
+
+        input
+    }
+}
+
+/// Faults that can be injected into the simulated bus to see how the port copes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// The device never pulls the acknowledge byte low, like a half-plugged controller
+    NoAck,
+    /// Something else on the bus talks over the address byte
+    Collision,
+    /// The ID and acknowledge come back as noise, like a bad cable
+    Garbled,
+}
+
+struct Card {
+    inserted: bool,
+    flags: Flags,
+    worn: Option<u16>,
+    pocket: Option<Pocket>,
+    data: [u8; CARD_SIZE],
+}
+
+/// The extra bits a PocketStation keeps track of
+#[derive(Clone, Copy)]
+struct Pocket {
+    application: u16,
+    launched: Option<(u16, u32)>,
+}
+
+impl Pocket {
+    /// 12:30:45 on Sunday the 18th of October, 2026, in BCD
+    const STATUS: [u8; 18] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x78, 0x56, 0x34, 0x12, 0x18, 0x10, 0x26, 0x20, 0x45,
+        0x30, 0x12, 0x00,
+    ];
+
+    fn reply(&self, received: &[u8], index: usize) -> u8 {
+        match (received[1], index) {
+            (0x58, 2) | (0x59, 2) | (0x5a, 2) => 0x5a,
+            (0x58, 3) | (0x59, 3) | (0x5a, 3) => 0x5d,
+
+            // Version, whatever it means
+            (0x58, 4) => 0x04,
+            (0x58, 5..=8) => [0x02, 0x00, 0x01, 0x00][index - 5],
+
+            (0x5a, 4) => Self::STATUS.len() as u8,
+            (0x5a, 5) => self.application as u8,
+            (0x5a, 6) => (self.application >> 8) as u8,
+            (0x5a, 7..=22) => Self::STATUS[index - 5],
+
+            (0x59, 4..=10) => 0x00,
+            _ => 0xff,
+        }
+    }
+
+    fn commit(&mut self, received: &[u8], length: usize) {
+        if received[1] == 0x59 && length >= 11 {
+            let application = u16::from_le_bytes([received[5], received[6]]);
+            let parameter =
+                u32::from_le_bytes([received[7], received[8], received[9], received[10]]);
+
+            self.application = application;
+            self.launched = Some((application, parameter));
+        }
+    }
+}
+
+impl Card {
+    const EMPTY: Self = Self {
+        inserted: false,
+        flags: Flags::FRESH,
+        worn: None,
+        pocket: None,
+        data: [0u8; CARD_SIZE],
+    };
+
+    /// The frame number sent with a read or write
+    fn frame(received: &[u8]) -> usize {
+        usize::from(received[4]) << 8 | usize::from(received[5])
+    }
+
+    /// What the card sends back at `index` of a transaction
+    fn reply(&self, received: &[u8], index: usize) -> u8 {
+        if !self.inserted {
+            return 0xff;
+        }
+
+        let frame = Self::frame(received);
+        let valid = frame < usize::from(FRAME_COUNT);
+        let data = &self.data[(frame % usize::from(FRAME_COUNT)) * FRAME_SIZE..][..FRAME_SIZE];
+
+        if let (Some(pocket), 0x58..=0x5a, 2..) = (self.pocket, received[1], index) {
+            return pocket.reply(received, index);
+        }
+
+        match (received[1], index) {
+            (_, 1) => self.flags.bits(),
+            (0x52, 2) | (0x53, 2) | (0x57, 2) => 0x5a,
+            (0x52, 3) | (0x53, 3) | (0x57, 3) => 0x5d,
+
+            // Read. An invalid frame number reads back as 0xFFFF and the card gives up
+            (0x52, 4) => 0x00,
+            (0x52, 5) => received[4],
+            (0x52, 6) => 0x5c,
+            (0x52, 7) => 0x5d,
+            (0x52, _) if !valid => 0xff,
+            (0x52, 8) => received[4],
+            (0x52, 9) => received[5],
+            (0x52, 10..=137) => data[index - 10],
+            (0x52, 138) => {
+                let checksum = data.iter().fold(received[4] ^ received[5], |a, x| a ^ x);
+
+                // A worn out frame never reads back the same way twice
+                match self.worn {
+                    Some(x) if usize::from(x) == frame => !checksum,
+                    _ => checksum,
+                }
+            }
+            (0x52, 139) => 0x47,
+
+            // Write. The card echoes back the previous byte while the data goes by
+            (0x57, 4) => 0x00,
+            (0x57, 5..=134) => received[index - 1],
+            (0x57, 135) => 0x5c,
+            (0x57, 136) => 0x5d,
+            (0x57, 137) => match Self::check_write(received) {
+                _ if !valid => 0xff,
+                true => 0x47,
+                false => 0x4e,
+            },
+
+            // Get ID
+            (0x53, 4) => 0x5c,
+            (0x53, 5) => 0x5d,
+            (0x53, 6) => 0x04,
+            (0x53, 7) => 0x00,
+            (0x53, 8) => 0x00,
+            (0x53, 9) => 0x80,
+
+            _ => 0xff,
+        }
+    }
+
+    fn check_write(received: &[u8]) -> bool {
+        let checksum = received[6..134]
+            .iter()
+            .fold(received[4] ^ received[5], |a, x| a ^ x);
+
+        checksum == received[134]
+    }
+
+    fn commit(&mut self, received: &[u8], length: usize) {
+        let frame = Self::frame(received);
+
+        if let (true, Some(pocket)) = (self.inserted, self.pocket.as_mut()) {
+            pocket.commit(received, length);
+        }
+
+        if !self.inserted || received[1] != 0x57 || length < 138 {
+            return;
+        }
+
+        if frame < usize::from(FRAME_COUNT) && Self::check_write(received) {
+            self.data[frame * FRAME_SIZE..][..FRAME_SIZE].copy_from_slice(&received[6..134]);
+            self.flags.remove(Flags::FRESH | Flags::WRITE_ERROR);
+        } else {
+            self.flags.insert(Flags::WRITE_ERROR);
+        }
+    }
+}
+
+struct State {
+    cards: [Card; CARD_SLOTS],
+    personality: Personality,
+    fault: Option<Fault>,
+    input: [u8; INPUT_LEN],
+
+    analog: bool,
+    locked: bool,
+    config: bool,
+    format: [u8; 3],
+    /// Which of the twelve pressures are being measured, as set with 0x40
+    pressures: u16,
+    actuators: [u8; 6],
+    motors: [u8; 6],
+
+    /// `None` until a select pin is used. Without one every transfer is
+    /// treated as a whole transaction.
+    selected: Option<bool>,
+    clocked: usize,
+    escapes: usize,
+    index: usize,
+    received: [u8; TRANSACTION_LEN],
+}
+
+impl State {
+    fn new(personality: Personality) -> Self {
+        let mut state = Self {
+            cards: [Card::EMPTY; CARD_SLOTS],
+            personality,
+            fault: None,
+            input: [0u8; INPUT_LEN],
+            analog: false,
+            locked: false,
+            config: false,
+            format: FORMAT_ANALOG,
+            pressures: PRESSURES_ALL,
+            actuators: [0xff; 6],
+            motors: [0x00; 6],
+            selected: None,
+            clocked: 0,
+            escapes: 0,
+            index: 0,
+            received: [0u8; TRANSACTION_LEN],
+        };
+
+        state.plug(personality);
+        state
+    }
+
+    /// Swap the controller for another, fresh from power on
+    fn plug(&mut self, personality: Personality) {
+        self.personality = personality;
+        self.input = personality.idle_input();
+        // The Guitar Hero controller is always in analog mode
+        self.analog = personality == Personality::GuitarHero;
+        self.locked = false;
+        self.config = false;
+        self.format = FORMAT_ANALOG;
+        self.pressures = PRESSURES_ALL;
+        self.actuators = [0xff; 6];
+        self.motors = [0x00; 6];
+    }
+
+    fn begin(&mut self) {
+        self.index = 0;
+        self.received = [0u8; TRANSACTION_LEN];
+    }
+
+    /// Which card slot the transaction is for, if any
+    fn slot(&self) -> Option<usize> {
+        match self.received[0].wrapping_sub(CARD_ADDRESS) {
+            x @ 1..=2 => Some(usize::from(x) - 1),
+            _ => None,
+        }
+    }
+
+    fn end(&mut self) {
+        if let Some(x) = self.slot() {
+            self.cards[x].commit(&self.received, self.index);
+        } else if self.index > 1 {
+            if self.command() == 0x43 {
+                self.escapes += 1;
+            }
+            self.commit();
+        }
+        self.begin();
+    }
+
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.clocked += 1;
+
+        if self.selected == Some(false) {
+            return 0xff;
+        }
+
+        let reply = self.reply(self.index);
+
+        if self.index < TRANSACTION_LEN {
+            self.received[self.index] = byte;
+        }
+        self.index += 1;
+
+        reply
+    }
+
+    fn command(&self) -> u8 {
+        self.received[1]
+    }
+
+    fn args(&self) -> &[u8] {
+        &self.received[HEADER_LEN..]
+    }
+
+    fn present(&self) -> bool {
+        self.received[0] == PAD_ADDRESS && self.personality != Personality::Missing
+    }
+
+    fn words(&self) -> u8 {
+        let mask = self.format_mask() & FORMAT_MASK;
+        mask.count_ones().div_ceil(2) as u8
+    }
+
+    fn format_mask(&self) -> u32 {
+        u32::from(self.format[0]) | u32::from(self.format[1]) << 8 | u32::from(self.format[2]) << 16
+    }
+
+    fn id(&self) -> u8 {
+        if self.config {
+            return 0xf3;
+        }
+
+        match self.personality {
+            Personality::Missing => 0xff,
+            Personality::Classic => 0xc1,
+            Personality::Mouse => 0x12,
+            Personality::Multitap => 0x80,
+            Personality::NegCon => 0x23,
+            Personality::GunCon => 0x63,
+            Personality::JogCon if self.analog => 0xe3,
+            Personality::DualShock | Personality::GuitarHero if self.analog => 0x73,
+            Personality::DualShock2 if self.analog => 0x70 | self.words(),
+            _ => 0x41,
+        }
+    }
+
+    fn supported(&self, command: u8) -> bool {
+        if !self.config {
+            return match command {
+                0x42 => true,
+                0x43 => self.personality.has_escape(),
+                _ => false,
+            };
+        }
+
+        match self.personality {
+            Personality::JogCon => matches!(command, 0x42..=0x47 | 0x4c | 0x4d),
+            Personality::DualShock => {
+                matches!(command, 0x40..=0x4a | 0x4c..=0x4e)
+            }
+            Personality::GuitarHero => matches!(command, 0x40..=0x4f),
+            Personality::DualShock2 => matches!(command, 0x40..=0x4f | 0xa0),
+            _ => false,
+        }
+    }
+
+    fn reply(&self, index: usize) -> u8 {
+        match (self.fault, index) {
+            (Some(Fault::Collision), 0) => return 0x00,
+            (Some(Fault::NoAck), 2) | (Some(Fault::Garbled), 2) => return 0xff,
+            (Some(Fault::Garbled), 1) => return 0x00,
+            _ => {}
+        }
+
+        if let (true, Some(x)) = (index > 0, self.slot()) {
+            return self.cards[x].reply(&self.received, index);
+        }
+
+        if index == 0 || !self.present() {
+            return 0xff;
+        }
+
+        if index == 1 {
+            return self.id();
+        }
+
+        if !self.supported(self.command()) {
+            return 0xff;
+        }
+
+        if index == 2 {
+            return 0x5a;
+        }
+
+        let mut payload = [0xffu8; PAYLOAD_LEN];
+        let len = if self.config {
+            self.config_payload(&mut payload)
+        } else {
+            self.poll_payload(&mut payload)
+        };
+
+        match index - HEADER_LEN {
+            n if n < len => payload[n],
+            _ => 0xff,
+        }
+    }
+
+    fn poll_payload(&self, payload: &mut [u8]) -> usize {
+        let len = usize::from(self.id() & 0x0f) * 2;
+
+        if self.personality == Personality::DualShock2 && self.analog {
+            let mask = self.format_mask();
+            let selected = (0..INPUT_LEN).filter(|i| mask & (1 << i) != 0);
+            for (slot, i) in selected.enumerate() {
+                // Pressures that aren't being measured always read as released
+                let measured =
+                    i < PRESSURE_START || self.pressures & (1 << (i - PRESSURE_START)) != 0;
+                payload[slot] = if measured { self.input[i] } else { 0x00 };
+            }
+        } else {
+            payload[..len].copy_from_slice(&self.input[..len]);
+        }
+
+        len
+    }
+
+    fn config_payload(&self, payload: &mut [u8]) -> usize {
+        let offset = self.args()[0];
+        let personality = self.personality;
+        let reply: [u8; CONFIG_LEN] = match self.command() {
+            0x40 if personality == Personality::DualShock2 && offset < 12 => {
+                [0x00, 0x00, 0x02, 0x00, 0x00, 0x5a]
+            }
+            0x41 if personality == Personality::DualShock2 && self.analog => [
+                self.format[0],
+                self.format[1],
+                self.format[2],
+                0x00,
+                0x00,
+                0x5a,
+            ],
+            0x42 => match personality {
+                Personality::DualShock2 => {
+                    let mut data = [0u8; CONFIG_LEN];
+                    data.copy_from_slice(&self.input[..CONFIG_LEN]);
+                    data
+                }
+                Personality::GuitarHero => [0x7f, 0xff, 0x7f, 0x7f, 0x7f, 0x7f],
+                _ => [0xff; CONFIG_LEN],
+            },
+            0x45 => {
+                let (generation, actuators) = match personality {
+                    Personality::DualShock2 => (0x03, 0x02),
+                    Personality::JogCon => (0x01, 0x01),
+                    _ => (0x01, 0x02),
+                };
+                // The Guitar Hero controller never lights its analog LED
+                let led = self.analog && personality != Personality::GuitarHero;
+
+                [generation, 0x02, led as u8, actuators, 0x01, 0x00]
+            }
+            0x46 => match (personality, offset) {
+                (Personality::JogCon, 0) => [0x00, 0x00, 0x04, 0x03, 0x01, 0x1e],
+                (Personality::JogCon, _) => [0x00; CONFIG_LEN],
+                (_, 0) => [0x00, 0x00, 0x01, 0x02, 0x00, 0x0a],
+                (_, 1) => [0x00, 0x00, 0x01, 0x01, 0x01, 0x14],
+                _ => [0x00; CONFIG_LEN],
+            },
+            0x47 => match personality {
+                Personality::JogCon => [0x00, 0x00, 0x01, 0x00, 0x00, 0x00],
+                _ => [0x00, 0x00, 0x02, 0x00, 0x01, 0x00],
+            },
+            0x48 => [0x00, 0x00, 0x00, 0x00, 0x01, 0x00],
+            0x4c => match (personality, offset) {
+                (_, 0) => [0x00, 0x00, 0x00, 0x04, 0x00, 0x00],
+                (Personality::DualShock2, 1) | (Personality::GuitarHero, 1) => {
+                    [0x00, 0x00, 0x00, 0x07, 0x00, 0x00]
+                }
+                (_, 1) => [0x00, 0x00, 0x00, 0x04, 0x00, 0x00],
+                _ => [0x00; CONFIG_LEN],
+            },
+            0x4d => self.actuators,
+            0xa0 => [0x05, 0x01, 0x02, 0x00, 0x00, 0x00],
+            _ => [0x00; CONFIG_LEN],
+        };
+
+        payload[..CONFIG_LEN].copy_from_slice(&reply);
+
+        CONFIG_LEN
+    }
+
+    /// Apply the side effects of a finished transaction
+    fn commit(&mut self) {
+        let command = self.command();
+        if !self.present() || !self.supported(command) {
+            return;
+        }
+
+        let mut args = [0u8; 6];
+        args.copy_from_slice(&self.args()[..6]);
+
+        match (self.config, command) {
+            (false, 0x42) => self.motors = args,
+            (false, 0x43) => self.config = args[0] == 0x01,
+            (true, 0x43) => self.config = args[0] == 0x01,
+            (true, 0x44) => {
+                // The Guitar Hero controller can't leave analog mode
+                if self.personality != Personality::GuitarHero {
+                    self.analog = args[0] == 0x01;
+                    self.format = FORMAT_ANALOG;
+                }
+                self.locked = args[1] == 0x03;
+            }
+            (true, 0x40) if self.personality == Personality::DualShock2 && args[0] < 12 => {
+                let bit = 1 << args[0];
+                if args[1] == 0x02 {
+                    self.pressures |= bit;
+                } else {
+                    self.pressures &= !bit;
+                }
+            }
+            (true, 0x4d) => self.actuators = args,
+            (true, 0x4f) if self.personality == Personality::DualShock2 => {
+                let mask =
+                    (u32::from(args[0]) | u32::from(args[1]) << 8 | u32::from(args[2]) << 16)
+                        & FORMAT_MASK;
+                self.format = [mask as u8, (mask >> 8) as u8, (mask >> 16) as u8];
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A simulated controller. Hand out its `bus()` and `select()` to a
+/// `PlayStationPort` and inspect or poke the simulated device from the test.
+pub struct Simulator {
+    state: RefCell<State>,
+}
+
+impl Simulator {
+    /// The card slot on a multi-tap port
+    fn slot(port: MultitapPort) -> usize {
+        match port {
+            MultitapPort::A => 0,
+            MultitapPort::B => 1,
+            _ => panic!("only ports A and B have room for a memory card"),
+        }
+    }
+
+    /// Create a simulator with the given device plugged in
+    pub fn new(personality: Personality) -> Self {
+        Self {
+            state: RefCell::new(State::new(personality)),
+        }
+    }
+
+    /// The SPI bus the simulated device is listening on
+    pub fn bus(&self) -> SimulatedBus<'_> {
+        SimulatedBus { sim: self }
+    }
+
+    /// The select (attention) line of the simulated device. Optional, as without
+    /// it every transfer is considered to be a complete transaction.
+    pub fn select(&self) -> SimulatedSelect<'_> {
+        SimulatedSelect { sim: self }
+    }
+
+    /// Unplug whatever is connected and plug in something else. The new device
+    /// starts in its power-on state.
+    pub fn plug(&self, personality: Personality) {
+        self.state.borrow_mut().plug(personality);
+    }
+
+    /// Slide a blank memory card in next to the controller. Like a real card it
+    /// will report itself as freshly inserted until the first write.
+    pub fn insert_card(&self) {
+        self.insert_card_on(MultitapPort::A);
+    }
+
+    /// Slide a blank memory card into a port of the multi-tap. Only ports A and B
+    /// have room for one.
+    pub fn insert_card_on(&self, port: MultitapPort) {
+        let card = &mut self.state.borrow_mut().cards[Self::slot(port)];
+
+        card.inserted = true;
+        card.flags = Flags::FRESH;
+        card.pocket = None;
+    }
+
+    /// Slide a PocketStation in next to the controller. It's a memory card like
+    /// any other, with a clock stuck at 12:30:45 on the 18th of October 2026 and
+    /// no application running.
+    pub fn insert_pocketstation(&self) {
+        self.insert_card();
+        self.state.borrow_mut().cards[0].pocket = Some(Pocket {
+            application: 0,
+            launched: None,
+        });
+    }
+
+    /// The application and parameter the PocketStation was last told to launch
+    pub fn pocketstation_launched(&self) -> Option<(u16, u32)> {
+        self.state.borrow().cards[0].pocket.and_then(|x| x.launched)
+    }
+
+    /// Pull the memory card out. Its contents are kept for when it's put back.
+    pub fn remove_card(&self) {
+        self.state.borrow_mut().cards[0].inserted = false;
+    }
+
+    /// Peek at a frame of the memory card
+    pub fn card_frame(&self, frame: u16) -> Frame {
+        self.card_frame_on(MultitapPort::A, frame)
+    }
+
+    /// Peek at a frame of the memory card on a port of the multi-tap
+    pub fn card_frame_on(&self, port: MultitapPort, frame: u16) -> Frame {
+        let mut data = [0u8; FRAME_SIZE];
+        let offset = usize::from(frame) * FRAME_SIZE;
+        let card = &self.state.borrow().cards[Self::slot(port)];
+
+        data.copy_from_slice(&card.data[offset..offset + FRAME_SIZE]);
+        data
+    }
+
+    /// Change a frame of the memory card behind the port's back
+    pub fn set_card_frame(&self, frame: u16, data: &Frame) {
+        let offset = usize::from(frame) * FRAME_SIZE;
+
+        self.state.borrow_mut().cards[0].data[offset..offset + FRAME_SIZE].copy_from_slice(data);
+    }
+
+    /// The memory card's FLAG byte
+    pub fn card_flags(&self) -> Flags {
+        self.state.borrow().cards[0].flags
+    }
+
+    /// Wear out a frame of the memory card so it always reads back with a bad
+    /// checksum, or pass `None` to make it good as new
+    pub fn set_worn_frame(&self, frame: Option<u16>) {
+        self.state.borrow_mut().cards[0].worn = frame;
+    }
+
+    /// Make the bus misbehave, or pass `None` to have it behave again
+    pub fn set_fault(&self, fault: Option<Fault>) {
+        self.state.borrow_mut().fault = fault;
+    }
+
+    /// The device currently plugged in
+    pub fn personality(&self) -> Personality {
+        self.state.borrow().personality
+    }
+
+    /// Set what the device reports. The bytes are laid out the way the device
+    /// sends them with every feature turned on: buttons, then sticks (or wheel,
+    /// twist, position...), then pressures. Missing bytes are left as they were.
+    pub fn set_input(&self, input: &[u8]) {
+        let mut state = self.state.borrow_mut();
+        let len = input.len().min(INPUT_LEN);
+
+        state.input[..len].copy_from_slice(&input[..len]);
+    }
+
+    /// Whether the device is currently in escape (configuration) mode
+    pub fn in_config_mode(&self) -> bool {
+        self.state.borrow().config
+    }
+
+    /// Whether the device is in analog mode
+    pub fn is_analog(&self) -> bool {
+        self.state.borrow().analog
+    }
+
+    /// Press the Analog button like a player would. It flips between digital
+    /// and analog mode unless the mode has been locked.
+    pub fn press_analog_button(&self) {
+        let mut state = self.state.borrow_mut();
+
+        let has_button = matches!(
+            state.personality,
+            Personality::DualShock | Personality::DualShock2
+        );
+        if has_button && !state.locked {
+            state.analog = !state.analog;
+            state.format = FORMAT_ANALOG;
+        }
+    }
+
+    /// Whether the analog mode button has been locked out
+    pub fn is_locked(&self) -> bool {
+        self.state.borrow().locked
+    }
+
+    /// The response format last set with command 0x4F
+    pub fn response_format(&self) -> [u8; 3] {
+        self.state.borrow().format
+    }
+
+    /// Which of the DualShock 2's pressures are measured, one bit each in the
+    /// order they're reported, as set with command 0x40
+    pub fn pressures(&self) -> u16 {
+        self.state.borrow().pressures
+    }
+
+    /// Which poll bytes drive which actuator, as set with command 0x4D
+    pub fn actuators(&self) -> [u8; 6] {
+        self.state.borrow().actuators
+    }
+
+    /// How many bytes have been clocked over the bus so far
+    pub fn clocked(&self) -> usize {
+        self.state.borrow().clocked
+    }
+
+    /// How many escape commands (0x43) have been sent, whether the device took
+    /// them or not
+    pub fn escapes(&self) -> usize {
+        self.state.borrow().escapes
+    }
+
+    /// The command bytes sent along with the last poll, usually motor values
+    pub fn motors(&self) -> [u8; 6] {
+        self.state.borrow().motors
+    }
+}
+
+/// The simulated SPI bus. Created with `Simulator::bus()`
+pub struct SimulatedBus<'a> {
+    sim: &'a Simulator,
+}
+
+impl<'a> SimulatedBus<'a> {
+    fn exchange(&mut self, words: &mut dyn Iterator<Item = (u8, Option<&mut u8>)>) {
+        let mut state = self.sim.state.borrow_mut();
+        let unmanaged = state.selected.is_none();
+
+        if unmanaged {
+            state.begin();
+        }
+
+        for (word, read) in words {
+            // The wire is LSB first, the simulator thinks MSB first
+            let reply = state.exchange(word.swap_bits()).swap_bits();
+            if let Some(x) = read {
+                *x = reply;
+            }
+        }
+
+        if unmanaged {
+            state.end();
+        }
+    }
+}
+
+impl<'a> spi::ErrorType for SimulatedBus<'a> {
+    type Error = Infallible;
+}
+
+impl<'a> SpiBus for SimulatedBus<'a> {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.exchange(&mut words.iter_mut().map(|x| (0x00, Some(x))));
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.exchange(&mut words.iter().map(|x| (*x, None)));
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        let len = read.len().max(write.len());
+        let mut read = read.iter_mut();
+        let mut write = write.iter();
+
+        self.exchange(&mut (0..len).map(|_| (*write.next().unwrap_or(&0x00), read.next())));
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.exchange(&mut words.iter_mut().map(|x| (*x, Some(x))));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The simulated select line. Created with `Simulator::select()`
+pub struct SimulatedSelect<'a> {
+    sim: &'a Simulator,
+}
+
+impl<'a> digital::ErrorType for SimulatedSelect<'a> {
+    type Error = Infallible;
+}
+
+impl<'a> OutputPin for SimulatedSelect<'a> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        let mut state = self.sim.state.borrow_mut();
+        state.begin();
+        state.selected = Some(true);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        let mut state = self.sim.state.borrow_mut();
+        if state.selected == Some(true) {
+            state.end();
+        }
+        state.selected = Some(false);
+        Ok(())
+    }
+}